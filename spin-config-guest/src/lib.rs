@@ -0,0 +1,40 @@
+use http_types::{RequestResult, Response};
+
+wit_bindgen::generate!({
+    world: "spin-http",
+    path: "../wit"
+});
+
+struct InboundHttp;
+
+impl inbound_http::InboundHttp for InboundHttp {
+    fn handle_request(req: RequestResult) -> Response {
+        assert_eq!("/foo?a=b", &req.uri);
+        assert_eq!(
+            &[("what".to_owned(), "up".to_owned())] as &[_],
+            &req.headers
+        );
+        assert_eq!(Some(b"hello, world!" as &[_]), req.body.as_deref());
+
+        assert!(matches!(
+            config::get_config("missing"),
+            Err(config::Error::InvalidKey(_))
+        ));
+
+        Response {
+            status: 200,
+            headers: Some(vec![(
+                "content-type".to_owned(),
+                config::get_config("content_type").unwrap(),
+            )]),
+            body: Some(config::get_config("message").unwrap().into_bytes()),
+        }
+    }
+}
+
+export_spin_http!(InboundHttp);
+
+#[export_name = "canonical_abi_free"]
+unsafe fn canonical_abi_free(_ptr: *mut u8, _size: usize, _align: usize) {
+    unreachable!()
+}
@@ -0,0 +1,29 @@
+use http_types::{RequestResult, Response};
+
+wit_bindgen::generate!({
+    world: "spin-http",
+    path: "../wit"
+});
+
+struct InboundHttp;
+
+impl inbound_http::InboundHttp for InboundHttp {
+    /// Echo the request's headers and body, plus a `uri-length` header so the host can check we saw the whole URI
+    fn handle_request(req: RequestResult) -> Response {
+        let mut headers = req.headers;
+        headers.push(("uri-length".to_owned(), req.uri.len().to_string()));
+
+        Response {
+            status: 200,
+            headers: Some(headers),
+            body: req.body,
+        }
+    }
+}
+
+export_spin_http!(InboundHttp);
+
+#[export_name = "canonical_abi_free"]
+unsafe fn canonical_abi_free(_ptr: *mut u8, _size: usize, _align: usize) {
+    unreachable!()
+}
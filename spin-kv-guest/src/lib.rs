@@ -0,0 +1,46 @@
+use http_types::{RequestResult, Response};
+
+wit_bindgen::generate!({
+    world: "spin-http",
+    path: "../wit"
+});
+
+struct InboundHttp;
+
+impl inbound_http::InboundHttp for InboundHttp {
+    fn handle_request(req: RequestResult) -> Response {
+        assert_eq!("/foo?a=b", &req.uri);
+        assert_eq!(
+            &[("what".to_owned(), "up".to_owned())] as &[_],
+            &req.headers
+        );
+
+        let body = req.body.unwrap();
+        assert_eq!(b"hello, world!" as &[_], &body);
+
+        let store = key_value::open("default").unwrap();
+
+        key_value::set(store, "foo", &body).unwrap();
+        assert!(key_value::exists(store, "foo").unwrap());
+        assert_eq!(body, key_value::get(store, "foo").unwrap());
+        assert!(key_value::get_keys(store)
+            .unwrap()
+            .iter()
+            .any(|key| key == "foo"));
+
+        key_value::close(store);
+
+        Response {
+            status: 200,
+            headers: Some(vec![("content-type".to_owned(), "text/plain".to_owned())]),
+            body: Some(b"hola, mundo!".to_vec()),
+        }
+    }
+}
+
+export_spin_http!(InboundHttp);
+
+#[export_name = "canonical_abi_free"]
+unsafe fn canonical_abi_free(_ptr: *mut u8, _size: usize, _align: usize) {
+    unreachable!()
+}
@@ -0,0 +1,43 @@
+use http_types::{Method, RequestParam, RequestResult, Response};
+
+wit_bindgen::generate!({
+    world: "spin-http",
+    path: "../wit"
+});
+
+struct InboundHttp;
+
+impl inbound_http::InboundHttp for InboundHttp {
+    fn handle_request(req: RequestResult) -> Response {
+        assert_eq!("/foo?a=b", &req.uri);
+        assert_eq!(
+            &[("what".to_owned(), "up".to_owned())] as &[_],
+            &req.headers
+        );
+        assert_eq!(Some(b"hello, world!" as &[_]), req.body.as_deref());
+
+        let response = http::send_request(RequestParam {
+            method: Method::Post,
+            uri: &config::get_config("backend_url").unwrap(),
+            headers: &[("what", "up")],
+            params: &[],
+            body: req.body.as_deref(),
+        })
+        .unwrap();
+
+        assert_eq!(200, response.status);
+
+        Response {
+            status: 200,
+            headers: Some(vec![("content-type".to_owned(), "text/plain".to_owned())]),
+            body: response.body,
+        }
+    }
+}
+
+export_spin_http!(InboundHttp);
+
+#[export_name = "canonical_abi_free"]
+unsafe fn canonical_abi_free(_ptr: *mut u8, _size: usize, _align: usize) {
+    unreachable!()
+}
@@ -0,0 +1,62 @@
+use http_types::{RequestResult, Response};
+
+wit_bindgen::generate!({
+    world: "spin-http",
+    path: "../wit"
+});
+
+const ADDRESS: &str = "redis://127.0.0.1:6379";
+
+struct InboundHttp;
+
+impl inbound_http::InboundHttp for InboundHttp {
+    fn handle_request(req: RequestResult) -> Response {
+        assert_eq!("/foo?a=b", &req.uri);
+        assert_eq!(
+            &[("what".to_owned(), "up".to_owned())] as &[_],
+            &req.headers
+        );
+
+        let body = req.body.unwrap();
+        assert_eq!(b"hello, world!" as &[_], &body);
+
+        redis::set(ADDRESS, "foo", &body).unwrap();
+        assert_eq!(body, redis::get(ADDRESS, "foo").unwrap());
+
+        let count = redis::incr(ADDRESS, "count").unwrap();
+        assert!(count > 0);
+
+        redis::sadd(ADDRESS, "set", &["what", "up"]).unwrap();
+        assert_eq!(2, redis::smembers(ADDRESS, "set").unwrap().len());
+        assert_eq!(1, redis::srem(ADDRESS, "set", &["up"]).unwrap());
+
+        assert!(matches!(
+            redis::execute(
+                ADDRESS,
+                "INCRBY",
+                &[
+                    redis_types::RedisParameter::Binary(b"count"),
+                    redis_types::RedisParameter::Int64(1)
+                ]
+            )
+            .unwrap()
+            .as_slice(),
+            &[redis_types::RedisResult::Int64(value)] if value == count + 1
+        ));
+
+        assert_eq!(2, redis::del(ADDRESS, &["foo", "set"]).unwrap());
+
+        Response {
+            status: 200,
+            headers: Some(vec![("content-type".to_owned(), "text/plain".to_owned())]),
+            body: Some(b"hola, mundo!".to_vec()),
+        }
+    }
+}
+
+export_spin_http!(InboundHttp);
+
+#[export_name = "canonical_abi_free"]
+unsafe fn canonical_abi_free(_ptr: *mut u8, _size: usize, _align: usize) {
+    unreachable!()
+}
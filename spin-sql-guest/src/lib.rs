@@ -0,0 +1,92 @@
+use {
+    http_types::{RequestResult, Response},
+    rdbms_types::{DbDataType, DbValue, ParameterValue},
+};
+
+wit_bindgen::generate!({
+    world: "spin-http",
+    path: "../wit"
+});
+
+const POSTGRES_ADDRESS: &str = "postgres://localhost/greetings";
+
+const MYSQL_ADDRESS: &str = "mysql://localhost/greetings";
+
+const CREATE: &str =
+    "CREATE TABLE IF NOT EXISTS greetings (id BIGINT PRIMARY KEY, greeting TEXT NOT NULL)";
+
+struct InboundHttp;
+
+impl inbound_http::InboundHttp for InboundHttp {
+    fn handle_request(req: RequestResult) -> Response {
+        assert_eq!("/foo?a=b", &req.uri);
+        assert_eq!(
+            &[("what".to_owned(), "up".to_owned())] as &[_],
+            &req.headers
+        );
+        assert_eq!(Some(b"hello, world!" as &[_]), req.body.as_deref());
+
+        postgres::execute(POSTGRES_ADDRESS, CREATE, &[]).unwrap();
+        assert_eq!(
+            1,
+            postgres::execute(
+                POSTGRES_ADDRESS,
+                "INSERT INTO greetings (id, greeting) VALUES ($1, $2) \
+                 ON CONFLICT (id) DO UPDATE SET greeting = excluded.greeting",
+                &[ParameterValue::Int64(1), ParameterValue::Str("hola")],
+            )
+            .unwrap()
+        );
+
+        mysql::execute(MYSQL_ADDRESS, CREATE, &[]).unwrap();
+        mysql::execute(
+            MYSQL_ADDRESS,
+            "REPLACE INTO greetings (id, greeting) VALUES (?, ?)",
+            &[ParameterValue::Int64(1), ParameterValue::Str("mundo")],
+        )
+        .unwrap();
+
+        let greeting = |rows: rdbms_types::RowSet| {
+            assert_eq!(1, rows.columns.len());
+            assert!(matches!(rows.columns[0].data_type, DbDataType::Str));
+            match rows.rows.as_slice() {
+                [row] => match row.as_slice() {
+                    [DbValue::Str(greeting)] => greeting.clone(),
+                    _ => panic!("unexpected row: {row:?}"),
+                },
+                rows => panic!("expected one row; got {}", rows.len()),
+            }
+        };
+
+        let hola = greeting(
+            postgres::query(
+                POSTGRES_ADDRESS,
+                "SELECT greeting FROM greetings WHERE id = $1",
+                &[ParameterValue::Int64(1)],
+            )
+            .unwrap(),
+        );
+
+        let mundo = greeting(
+            mysql::query(
+                MYSQL_ADDRESS,
+                "SELECT greeting FROM greetings WHERE id = ?",
+                &[ParameterValue::Int64(1)],
+            )
+            .unwrap(),
+        );
+
+        Response {
+            status: 200,
+            headers: Some(vec![("content-type".to_owned(), "text/plain".to_owned())]),
+            body: Some(format!("{hola}, {mundo}!").into_bytes()),
+        }
+    }
+}
+
+export_spin_http!(InboundHttp);
+
+#[export_name = "canonical_abi_free"]
+unsafe fn canonical_abi_free(_ptr: *mut u8, _size: usize, _align: usize) {
+    unreachable!()
+}
@@ -0,0 +1,174 @@
+//! Stored baselines: latency results saved by one run, against which later runs are compared
+//!
+//! A baseline file has the same format as the runner's `--json` output, so any earlier JSON results can be used as
+//! a baseline.
+
+use {
+    super::bencher::Summary,
+    anyhow::{bail, Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{collections::HashMap, fs, path::Path},
+};
+
+/// Critical value of the normal distribution for a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    scenario: String,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+/// Read a baseline file, returning each scenario's summary keyed by name
+///
+/// Comparisons are relative to the baseline's mean, so entries without a positive mean (or without any iterations)
+/// are rejected rather than producing infinite or meaningless changes.
+pub fn load(path: &Path) -> Result<HashMap<String, Summary>> {
+    let entries = serde_json::from_slice::<Vec<Entry>>(
+        &fs::read(path).with_context(|| format!("unable to read baseline {}", path.display()))?,
+    )
+    .with_context(|| format!("unable to parse baseline {}", path.display()))?;
+
+    entries
+        .into_iter()
+        .map(|Entry { scenario, summary }| {
+            if summary.mean_ns <= 0.0 || summary.iterations == 0 {
+                bail!(
+                    "baseline {} has an invalid entry for {scenario}: mean {} ns over {} iteration(s)",
+                    path.display(),
+                    summary.mean_ns,
+                    summary.iterations
+                );
+            }
+            Ok((scenario, summary))
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    /// The whole confidence interval is faster than the baseline by more than the threshold
+    Improved,
+
+    /// The whole confidence interval is slower than the baseline by more than the threshold
+    Regressed,
+
+    /// Neither of the above, i.e. any change is either within the threshold or within the noise
+    Unchanged,
+}
+
+/// The change in a scenario's mean latency relative to its baseline
+#[derive(Copy, Clone, Debug)]
+pub struct Comparison {
+    /// Relative change in the mean, e.g. 0.1 for 10% slower
+    pub change: f64,
+
+    /// Half-width of the 95% confidence interval around `change`, on the same scale
+    pub margin: f64,
+
+    pub verdict: Verdict,
+}
+
+impl Comparison {
+    /// Compare `current` to `baseline`, where `threshold` is the relative change (e.g. 0.05 for 5%) beyond which
+    /// a difference is considered significant
+    ///
+    /// The confidence interval is Welch's (i.e. not assuming equal variances), using the normal approximation,
+    /// which is reasonable given the iteration counts we typically see.
+    pub fn new(baseline: &Summary, current: &Summary, threshold: f64) -> Self {
+        let variance = |summary: &Summary| {
+            summary.stddev_ns * summary.stddev_ns / summary.iterations.max(1) as f64
+        };
+
+        let change = (current.mean_ns - baseline.mean_ns) / baseline.mean_ns;
+        let margin = Z_95 * (variance(baseline) + variance(current)).sqrt() / baseline.mean_ns;
+
+        let verdict = if change - margin > threshold {
+            Verdict::Regressed
+        } else if change + margin < -threshold {
+            Verdict::Improved
+        } else {
+            Verdict::Unchanged
+        };
+
+        Self {
+            change,
+            margin,
+            verdict,
+        }
+    }
+}
+
+#[test]
+fn baseline_comparison() -> Result<()> {
+    let summary = |mean_ns: f64, stddev_ns: f64| Summary {
+        iterations: 100,
+        mean_ns,
+        stddev_ns,
+        min_ns: 0,
+        p50_ns: mean_ns as u64,
+        p90_ns: mean_ns as u64,
+        p99_ns: mean_ns as u64,
+        p999_ns: mean_ns as u64,
+        max_ns: mean_ns as u64,
+    };
+
+    let baseline = summary(1000.0, 50.0);
+
+    let comparison = Comparison::new(&baseline, &summary(1200.0, 50.0), 0.05);
+    assert_eq!(Verdict::Regressed, comparison.verdict);
+    assert!((comparison.change - 0.2).abs() < 1e-9);
+    assert!((comparison.margin - 1.96 * 50.0_f64.sqrt() / 1000.0).abs() < 1e-9);
+
+    assert_eq!(
+        Verdict::Improved,
+        Comparison::new(&baseline, &summary(800.0, 50.0), 0.05).verdict
+    );
+
+    // Within the threshold
+    assert_eq!(
+        Verdict::Unchanged,
+        Comparison::new(&baseline, &summary(1030.0, 50.0), 0.05).verdict
+    );
+
+    // Beyond the threshold, but within the noise
+    assert_eq!(
+        Verdict::Unchanged,
+        Comparison::new(&baseline, &summary(1200.0, 2000.0), 0.05).verdict
+    );
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("baseline.json");
+    fs::write(
+        &path,
+        serde_json::to_vec(&[Entry {
+            scenario: "foo".into(),
+            summary: baseline,
+        }])?,
+    )?;
+
+    let loaded = load(&path)?;
+    assert_eq!(1, loaded.len());
+    assert_eq!(100, loaded["foo"].iterations);
+    assert!(load(&dir.path().join("missing.json")).is_err());
+
+    for invalid in [
+        summary(0.0, 0.0),
+        Summary {
+            iterations: 0,
+            ..summary(1000.0, 50.0)
+        },
+    ] {
+        fs::write(
+            &path,
+            serde_json::to_vec(&[Entry {
+                scenario: "foo".into(),
+                summary: invalid,
+            }])?,
+        )?;
+        assert!(load(&path).is_err());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Stable-Rust replacement for libtest's `Bencher`, with configurable warm-up and time budgets
+//!
+//! Each timed iteration is recorded in an [HDR histogram](http://hdrhistogram.org/), so we can report tail latency
+//! as well as the mean without memory use growing with the iteration count.
+
+use {
+    hdrhistogram::Histogram,
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt::Write,
+        hint,
+        time::{Duration, Instant},
+    },
+};
+
+/// Controls how long each scenario runs
+#[derive(Copy, Clone)]
+pub struct Options {
+    /// How long to run a scenario before recording any timings
+    pub warm_up: Duration,
+
+    /// How long to spend recording timings once warm-up is complete
+    pub budget: Duration,
+
+    /// Minimum number of timed iterations, even if that means exceeding `budget`
+    pub min_iterations: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            warm_up: Duration::from_secs(1),
+            budget: Duration::from_secs(5),
+            min_iterations: 10,
+        }
+    }
+}
+
+/// Summary statistics for the timed iterations of a scenario, in nanoseconds
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Summary {
+    pub iterations: u64,
+    pub mean_ns: f64,
+    pub stddev_ns: f64,
+    pub min_ns: u64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub max_ns: u64,
+}
+
+impl Summary {
+    pub(crate) fn from_histogram(histogram: &Histogram<u64>) -> Self {
+        Self {
+            iterations: histogram.len(),
+            mean_ns: histogram.mean(),
+            stddev_ns: histogram.stdev(),
+            min_ns: histogram.min(),
+            p50_ns: histogram.value_at_quantile(0.5),
+            p90_ns: histogram.value_at_quantile(0.9),
+            p99_ns: histogram.value_at_quantile(0.99),
+            p999_ns: histogram.value_at_quantile(0.999),
+            max_ns: histogram.max(),
+        }
+    }
+}
+
+/// Create a histogram which resizes as needed and distinguishes values to three significant figures
+pub(crate) fn new_histogram() -> Histogram<u64> {
+    Histogram::new(3).unwrap()
+}
+
+/// Passed to each scenario, which calls `iter` with the code to be measured
+pub struct Bencher {
+    options: Options,
+    histogram: Histogram<u64>,
+}
+
+impl Bencher {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            histogram: new_histogram(),
+        }
+    }
+
+    /// Call `f` repeatedly, first to warm up and then to record how long each call takes
+    pub fn iter<T>(&mut self, mut f: impl FnMut() -> T) {
+        self.iter_with_setup(|| (), |()| f())
+    }
+
+    /// Like `iter`, but call `setup` before each call to `f`, passing its result to `f` and excluding it from the
+    /// timings
+    pub fn iter_with_setup<S, T>(
+        &mut self,
+        mut setup: impl FnMut() -> S,
+        mut f: impl FnMut(S) -> T,
+    ) {
+        let start = Instant::now();
+        while start.elapsed() < self.options.warm_up {
+            let input = setup();
+            hint::black_box(f(input));
+        }
+
+        self.histogram.reset();
+
+        let start = Instant::now();
+        while start.elapsed() < self.options.budget
+            || self.histogram.len() < self.options.min_iterations
+        {
+            let input = setup();
+            let iteration = Instant::now();
+            hint::black_box(f(input));
+            self.histogram.saturating_record(
+                iteration
+                    .elapsed()
+                    .as_nanos()
+                    .try_into()
+                    .unwrap_or(u64::MAX),
+            );
+        }
+    }
+
+    /// Summarize the timings recorded by `iter`, or return `None` if it was never called
+    pub fn summary(&self) -> Option<Summary> {
+        (!self.histogram.is_empty()).then(|| Summary::from_histogram(&self.histogram))
+    }
+
+    /// The latency of each timed iteration, in nanoseconds
+    pub fn histogram(&self) -> &Histogram<u64> {
+        &self.histogram
+    }
+}
+
+/// Render a histogram in the percentile distribution format used by HdrHistogram's other implementations (and
+/// understood by its plotting tools), with values in nanoseconds
+pub fn percentile_distribution(histogram: &Histogram<u64>) -> String {
+    let mut output = format!(
+        "{:>12} {:>14} {:>10} {:>14}\n\n",
+        "Value", "Percentile", "TotalCount", "1/(1-Percentile)"
+    );
+
+    let mut total = 0;
+    for value in histogram.iter_quantiles(5) {
+        total += value.count_since_last_iteration();
+        let quantile = value.quantile_iterated_to();
+        let _ = write!(
+            output,
+            "{:>12} {:>14.12} {:>10}",
+            value.value_iterated_to(),
+            quantile,
+            total
+        );
+        if quantile < 1.0 {
+            let _ = write!(output, " {:>14.2}", 1.0 / (1.0 - quantile));
+        }
+        output.push('\n');
+    }
+
+    let _ = writeln!(
+        output,
+        "#[Mean    = {:>12.3}, StdDeviation   = {:>12.3}]",
+        histogram.mean(),
+        histogram.stdev()
+    );
+    let _ = writeln!(
+        output,
+        "#[Max     = {:>12}, Total count    = {:>12}]",
+        histogram.max(),
+        histogram.len()
+    );
+
+    output
+}
+
+#[test]
+fn bencher_summary() {
+    let mut bencher = Bencher::new(Options {
+        warm_up: Duration::ZERO,
+        budget: Duration::ZERO,
+        min_iterations: 3,
+    });
+
+    assert!(bencher.summary().is_none());
+
+    let mut calls = 0;
+    bencher.iter(|| calls += 1);
+    assert_eq!(3, calls);
+
+    let mut setups = 0;
+    let mut inputs = Vec::new();
+    bencher.iter_with_setup(
+        || {
+            setups += 1;
+            setups
+        },
+        |input| inputs.push(input),
+    );
+    assert_eq!(vec![1, 2, 3], inputs);
+
+    let summary = bencher.summary().unwrap();
+    assert_eq!(3, summary.iterations);
+    assert!(summary.min_ns <= summary.p50_ns && summary.p50_ns <= summary.max_ns);
+
+    let mut histogram = new_histogram();
+    for nanos in 1..=1000 {
+        histogram.record(nanos).unwrap();
+    }
+
+    let summary = Summary::from_histogram(&histogram);
+    assert_eq!(1000, summary.iterations);
+    assert_eq!(1, summary.min_ns);
+    assert_eq!(500, summary.p50_ns);
+    assert_eq!(900, summary.p90_ns);
+    assert_eq!(990, summary.p99_ns);
+    assert_eq!(999, summary.p999_ns);
+    assert_eq!(1000, summary.max_ns);
+    assert!((summary.mean_ns - 500.5).abs() < 1e-9);
+
+    let distribution = percentile_distribution(&histogram);
+    assert!(distribution.starts_with("       Value"));
+    assert!(distribution.contains("#[Max     =         1000, Total count    =         1000]"));
+}
@@ -0,0 +1,841 @@
+//! Benchmark runner which works on stable Rust, e.g.:
+//!
+//! ```shell
+//! cargo run --release --bin runner -- spin_rust --budget 10 --json results.json
+//! ```
+
+use {
+    anyhow::{anyhow, bail, Context, Result},
+    clap::Parser,
+    serde::Serialize,
+    serde_json::Value,
+    std::{
+        env,
+        fmt::Display,
+        fs,
+        panic::{self, AssertUnwindSafe},
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        thread,
+        time::Duration,
+    },
+    wasmtime_performance::{
+        baseline::{self, Comparison, Verdict},
+        bencher::{self, Bencher, Options, Summary},
+        cold_start::{summarize, ColdStartScenario, Sample, COLD_START_SCENARIOS},
+        memory::{Footprint, MemoryScenario, MEMORY_SCENARIOS},
+        phases::{Phase, PhaseScenario, PHASE_SCENARIOS},
+        scenario_file::ScenarioFile,
+        size_sweep::{Dimension, SweepScenario, DIMENSIONS, SWEEP_SCENARIOS},
+        throughput::{Throughput, ThroughputScenario, THROUGHPUT_SCENARIOS},
+        Scenario, SCENARIOS,
+    },
+};
+
+#[derive(Parser)]
+struct Args {
+    /// Only run scenarios whose names contain at least one of these strings
+    filters: Vec<String>,
+
+    /// Require filters to match scenario names exactly rather than as substrings
+    #[arg(long)]
+    exact: bool,
+
+    /// Skip scenarios whose names contain this string (may be repeated)
+    #[arg(long)]
+    skip: Vec<String>,
+
+    /// List the selected scenarios without running them
+    #[arg(long)]
+    list: bool,
+
+    /// Seconds to run each scenario before recording timings
+    #[arg(long, default_value_t = 1.0)]
+    warm_up: f64,
+
+    /// Seconds to spend recording timings for each scenario
+    #[arg(long, default_value_t = 5.0)]
+    budget: f64,
+
+    /// Minimum number of timed iterations per scenario, regardless of `--budget`
+    #[arg(long, default_value_t = 10)]
+    min_iterations: u64,
+
+    /// Write results to this file as JSON
+    #[arg(long)]
+    json: Option<PathBuf>,
+
+    /// Write results to this file as CSV
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Directory to read declarative scenario files (`*.toml` and `*.json`) from, in addition to the built-in
+    /// scenarios; defaults to this package's `scenarios` directory
+    #[arg(long, value_name = "DIR")]
+    scenario_dir: Option<PathBuf>,
+
+    /// Save results to this file for later runs to compare against using `--baseline`
+    #[arg(long, value_name = "PATH")]
+    save_baseline: Option<PathBuf>,
+
+    /// Compare results against a file previously written by `--save-baseline` (or `--json`)
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<PathBuf>,
+
+    /// Percent change in mean latency beyond which a difference from the baseline is considered significant; the
+    /// runner fails if any scenario is significantly slower
+    #[arg(long, default_value_t = 5.0)]
+    threshold: f64,
+
+    /// Write each scenario's full latency histogram to `<DIR>/<SCENARIO>.hgrm`
+    #[arg(long, value_name = "DIR")]
+    histograms: Option<PathBuf>,
+
+    /// Measure requests per second with concurrent workers instead of per-request latency
+    #[arg(long)]
+    throughput: bool,
+
+    /// Comma-separated worker counts to measure throughput with; defaults to powers of two up to the number of
+    /// available cores, plus that number
+    #[arg(long, value_delimiter = ',')]
+    concurrency: Vec<usize>,
+
+    /// Measure the first request after process start, and a request after an idle period, using a new process
+    /// for each sample
+    #[arg(long)]
+    cold_start: bool,
+
+    /// Number of processes to start per cold-start scenario
+    #[arg(long, default_value_t = 10)]
+    samples: usize,
+
+    /// Seconds to leave each cold-start process idle before measuring the "after idle" request
+    #[arg(long, default_value_t = 1.0)]
+    idle: f64,
+
+    /// Measure memory use per live instance and page faults per request, using a new process for each scenario
+    #[arg(long)]
+    memory: bool,
+
+    /// Number of live instances to measure memory use with
+    #[arg(long, default_value_t = 100)]
+    instances: usize,
+
+    /// Measure latency across a range of body sizes, header counts, and URI lengths; filters are matched against
+    /// `<SCENARIO>/<DIMENSION>/<VALUE>`, e.g. `spin_rust_echo_sweep_pre_instance/body/1024`
+    #[arg(long)]
+    sweep: bool,
+
+    /// Time each phase of the request lifecycle (e.g. instantiation, the call itself, and dropping the store)
+    /// separately, aggregated across iterations
+    #[arg(long)]
+    phases: bool,
+
+    /// Internal: measure a single cold-start sample for the named scenario and print it as JSON
+    #[arg(long, hide = true, value_name = "SCENARIO")]
+    cold_start_child: Option<String>,
+
+    /// Internal: directory containing artifacts prepared for cold-start child processes
+    #[arg(long, hide = true)]
+    cold_start_dir: Option<PathBuf>,
+
+    /// Internal: measure the memory footprint of the named scenario and print it as JSON
+    #[arg(long, hide = true, value_name = "SCENARIO")]
+    memory_child: Option<String>,
+}
+
+impl Args {
+    fn selects(&self, name: &str) -> bool {
+        (self.filters.is_empty()
+            || self.filters.iter().any(|filter| {
+                if self.exact {
+                    name == filter.as_str()
+                } else {
+                    name.contains(filter.as_str())
+                }
+            }))
+            && !self.skip.iter().any(|skip| name.contains(skip.as_str()))
+    }
+}
+
+/// Anything a mode runs which can be selected by name
+trait Named {
+    fn name(&self) -> &str;
+}
+
+/// A latency scenario, either built in or read from a scenario file
+enum LatencyScenario<'a> {
+    BuiltIn(&'static Scenario),
+    File(&'a ScenarioFile),
+}
+
+impl<'a> LatencyScenario<'a> {
+    fn name(&self) -> &'a str {
+        match self {
+            Self::BuiltIn(scenario) => scenario.name,
+            Self::File(scenario) => &scenario.name,
+        }
+    }
+
+    fn run(&self, bencher: &mut Bencher) -> Result<()> {
+        match self {
+            Self::BuiltIn(scenario) => (scenario.run)(bencher),
+            Self::File(scenario) => scenario.run(bencher),
+        }
+    }
+}
+
+impl Named for LatencyScenario<'_> {
+    fn name(&self) -> &str {
+        LatencyScenario::name(self)
+    }
+}
+
+impl Named for &ThroughputScenario {
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+impl Named for &ColdStartScenario {
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+impl Named for &MemoryScenario {
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+impl Named for &PhaseScenario {
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+/// A sweep scenario at a single point in one dimension
+struct SweepPoint {
+    scenario: &'static SweepScenario,
+    dimension: Dimension,
+    value: usize,
+    name: String,
+}
+
+impl Named for SweepPoint {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    scenario: &'a str,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+#[derive(Serialize)]
+struct ThroughputRecord {
+    scenario: &'static str,
+    #[serde(flatten)]
+    throughput: Throughput,
+    /// Throughput relative to that of the smallest worker count measured
+    scaling: f64,
+}
+
+/// The time spent in one phase of a scenario, for cold-start and phase breakdowns
+#[derive(Serialize)]
+struct PhaseRecord {
+    scenario: &'static str,
+    phase: &'static str,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+#[derive(Serialize)]
+struct MemoryRecord {
+    scenario: &'static str,
+    #[serde(flatten)]
+    footprint: Footprint,
+}
+
+#[derive(Serialize)]
+struct SweepRecord {
+    scenario: &'static str,
+    dimension: &'static str,
+    value: usize,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+/// Write `records` to `path` as CSV, with a column for each field, naming the fields of nested (but not flattened)
+/// structs `<FIELD>_<NESTED>`
+fn write_csv(path: &Path, records: &[impl Serialize]) -> Result<()> {
+    let mut csv = String::new();
+    for (index, record) in records.iter().enumerate() {
+        let mut columns = Vec::new();
+        flatten(String::new(), serde_json::to_value(record)?, &mut columns);
+
+        let (names, values): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+        if index == 0 {
+            csv.push_str(&names.join(","));
+            csv.push('\n');
+        }
+        csv.push_str(&values.join(","));
+        csv.push('\n');
+    }
+    Ok(fs::write(path, csv)?)
+}
+
+/// Convert a record (or one of its fields) into CSV columns
+fn flatten(name: String, value: Value, columns: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                let name = if name.is_empty() {
+                    field
+                } else {
+                    format!("{name}_{field}")
+                };
+                flatten(name, value, columns);
+            }
+        }
+        Value::String(value) => columns.push((name, value)),
+        value => columns.push((name, value.to_string())),
+    }
+}
+
+/// Where a scenario reports its results as it runs
+struct Report<'a, R> {
+    name: &'a str,
+    width: usize,
+    records: &'a mut Vec<R>,
+}
+
+impl<R> Report<'_, R> {
+    /// Print a line of results next to the scenario's name, and keep `record` for the JSON and CSV output
+    fn push(&mut self, line: impl Display, record: R) {
+        println!("{:width$} ... {line}", self.name, width = self.width);
+        self.records.push(record);
+    }
+
+    /// Print a further line of results, aligned with those printed by `push`
+    fn note(&self, line: impl Display) {
+        println!("{:width$}     {line}", "", width = self.width);
+    }
+}
+
+/// Filter `scenarios` according to `args`, returning `None` (after printing their names) if they should only be
+/// listed rather than run
+fn select<S: Named>(args: &Args, scenarios: impl IntoIterator<Item = S>) -> Option<Vec<S>> {
+    let scenarios = scenarios
+        .into_iter()
+        .filter(|scenario| args.selects(scenario.name()))
+        .collect::<Vec<_>>();
+
+    if args.list {
+        for scenario in scenarios {
+            println!("{}", scenario.name());
+        }
+        None
+    } else {
+        Some(scenarios)
+    }
+}
+
+/// Run each scenario using `measure`, converting any panic (e.g. a failed assertion) into an error, then write the
+/// records it reports to the JSON, CSV, and baseline files specified by `args`, returning the names of any which
+/// failed
+fn drive<S: Named, R: Serialize>(
+    args: &Args,
+    scenarios: &[S],
+    mut measure: impl FnMut(&S, &mut Report<R>) -> Result<()>,
+) -> Result<Vec<String>> {
+    let width = scenarios
+        .iter()
+        .map(|scenario| scenario.name().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut records = Vec::new();
+    let mut failures = Vec::new();
+
+    for scenario in scenarios {
+        let name = scenario.name();
+        let mut report = Report {
+            name,
+            width,
+            records: &mut records,
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| measure(scenario, &mut report)))
+            .map_err(|_| anyhow!("scenario panicked"))
+            .and_then(|result| result);
+
+        if let Err(e) = result {
+            println!("{name:width$} ... FAILED: {e:?}");
+            failures.push(name.to_owned());
+        }
+    }
+
+    // A baseline is just JSON results, which `main` only allows for latency scenarios
+    for path in [&args.json, &args.save_baseline].into_iter().flatten() {
+        fs::write(path, serde_json::to_vec_pretty(&records)?)?;
+    }
+
+    if let Some(path) = &args.csv {
+        write_csv(path, &records)?;
+    }
+
+    Ok(failures)
+}
+
+/// Powers of two up to the number of available cores, plus that number if it isn't a power of two
+fn default_concurrency() -> Vec<usize> {
+    let cores = thread::available_parallelism().map_or(1, usize::from);
+    let mut concurrency = (0..)
+        .map(|exponent| 1 << exponent)
+        .take_while(|&workers| workers <= cores)
+        .collect::<Vec<_>>();
+    if !cores.is_power_of_two() {
+        concurrency.push(cores);
+    }
+    concurrency
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    // This should happen before anything else, so the child measures a truly cold start
+    if let Some(name) = &args.cold_start_child {
+        return cold_start_child(&args, name);
+    }
+
+    if let Some(name) = &args.memory_child {
+        return memory_child(&args, name);
+    }
+
+    let options = Options {
+        warm_up: Duration::from_secs_f64(args.warm_up),
+        budget: Duration::from_secs_f64(args.budget),
+        min_iterations: args.min_iterations,
+    };
+
+    if (args.cold_start || args.memory || args.phases || args.throughput || args.sweep)
+        && (args.baseline.is_some() || args.save_baseline.is_some())
+    {
+        bail!("baselines are only supported for latency scenarios");
+    }
+
+    let files;
+    let failures = if args.cold_start {
+        cold_start(&args)?
+    } else if args.memory {
+        memory(&args)?
+    } else if args.phases {
+        phases(&args, options)?
+    } else if args.sweep {
+        sweep(&args, options)?
+    } else if args.throughput {
+        throughput(&args, options)?
+    } else {
+        files = ScenarioFile::read_dir(
+            args.scenario_dir
+                .as_deref()
+                .unwrap_or(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios"))),
+        )?;
+        latency(&args, options, &files)?
+    };
+
+    if !failures.is_empty() {
+        bail!(
+            "{} scenario(s) failed: {}",
+            failures.len(),
+            failures.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Run the selected latency scenarios, including those read from scenario `files`, returning the names of any
+/// which failed
+fn latency(args: &Args, options: Options, files: &[ScenarioFile]) -> Result<Vec<String>> {
+    for (index, file) in files.iter().enumerate() {
+        if SCENARIOS.iter().any(|scenario| scenario.name == file.name)
+            || files[..index].iter().any(|other| other.name == file.name)
+        {
+            bail!("duplicate scenario name: {:?}", file.name);
+        }
+    }
+
+    let Some(scenarios) = select(
+        args,
+        SCENARIOS
+            .iter()
+            .map(LatencyScenario::BuiltIn)
+            .chain(files.iter().map(LatencyScenario::File)),
+    ) else {
+        return Ok(Vec::new());
+    };
+
+    if let Some(dir) = &args.histograms {
+        fs::create_dir_all(dir)?;
+    }
+
+    let baseline = args.baseline.as_deref().map(baseline::load).transpose()?;
+    let mut regressions = Vec::new();
+
+    let failures = drive(args, &scenarios, |scenario, report| {
+        let name = scenario.name();
+        let mut bencher = Bencher::new(options);
+        scenario.run(&mut bencher)?;
+        let summary = bencher
+            .summary()
+            .ok_or_else(|| anyhow!("scenario did not call `Bencher::iter`"))?;
+
+        if let Some(dir) = &args.histograms {
+            fs::write(
+                dir.join(format!("{name}.hgrm")),
+                bencher::percentile_distribution(bencher.histogram()),
+            )?;
+        }
+
+        let comparison = baseline
+            .as_ref()
+            .and_then(|baseline| baseline.get(name))
+            .map(|previous| Comparison::new(previous, &summary, args.threshold / 100.0));
+
+        report.push(
+            format!(
+                "{:>12.0} ns/iter (+/- {:.0}) p50 {} p90 {} p99 {} p99.9 {} max {} (n = {})",
+                summary.mean_ns,
+                summary.stddev_ns,
+                summary.p50_ns,
+                summary.p90_ns,
+                summary.p99_ns,
+                summary.p999_ns,
+                summary.max_ns,
+                summary.iterations
+            ),
+            Record {
+                scenario: name,
+                summary,
+            },
+        );
+
+        if let Some(comparison) = comparison {
+            report.note(format!(
+                "{:>+11.2}% vs. baseline (+/- {:.2}%): {}",
+                comparison.change * 100.0,
+                comparison.margin * 100.0,
+                match comparison.verdict {
+                    Verdict::Improved => "improved",
+                    Verdict::Regressed => "REGRESSED",
+                    Verdict::Unchanged => "no significant change",
+                }
+            ));
+            if comparison.verdict == Verdict::Regressed {
+                regressions.push(name);
+            }
+        }
+
+        Ok(())
+    })?;
+
+    if !regressions.is_empty() {
+        bail!(
+            "{} scenario(s) regressed by more than {}% relative to the baseline: {}",
+            regressions.len(),
+            args.threshold,
+            regressions.join(", ")
+        );
+    }
+
+    Ok(failures)
+}
+
+/// Run the selected throughput scenarios at each worker count, returning the names of any which failed
+fn throughput(args: &Args, options: Options) -> Result<Vec<String>> {
+    let Some(scenarios) = select(args, THROUGHPUT_SCENARIOS) else {
+        return Ok(Vec::new());
+    };
+
+    let mut concurrency = if args.concurrency.is_empty() {
+        default_concurrency()
+    } else {
+        args.concurrency.clone()
+    };
+    concurrency.sort_unstable();
+    concurrency.dedup();
+    if concurrency.first() == Some(&0) {
+        bail!("worker counts must be at least one");
+    }
+
+    drive(args, &scenarios, |scenario, report| {
+        let mut baseline = None;
+
+        for &workers in &concurrency {
+            let throughput = scenario
+                .measure(workers, options)
+                .with_context(|| format!("{workers} worker(s)"))?;
+
+            let baseline = *baseline.get_or_insert(throughput.requests_per_second);
+            let scaling = throughput.requests_per_second / baseline;
+
+            report.push(
+                format!(
+                    "{workers:>3} worker(s): {:>12.0} req/s ({scaling:.2}x)",
+                    throughput.requests_per_second
+                ),
+                ThroughputRecord {
+                    scenario: scenario.name,
+                    throughput,
+                    scaling,
+                },
+            );
+        }
+
+        Ok(())
+    })
+}
+
+fn cold_start_child(args: &Args, name: &str) -> Result<()> {
+    let scenario = COLD_START_SCENARIOS
+        .iter()
+        .find(|scenario| scenario.name == name)
+        .ok_or_else(|| anyhow!("unknown cold-start scenario: {name}"))?;
+
+    let dir = args
+        .cold_start_dir
+        .as_deref()
+        .ok_or_else(|| anyhow!("--cold-start-dir is required"))?;
+
+    let sample = scenario.measure(dir, Duration::from_secs_f64(args.idle))?;
+
+    println!("{}", serde_json::to_string(&sample)?);
+
+    Ok(())
+}
+
+/// Measure a cold-start sample for the named scenario in a new process
+fn cold_start_sample(args: &Args, name: &str, dir: &Path) -> Result<Sample> {
+    let output = Command::new(env::current_exe()?)
+        .arg("--cold-start-child")
+        .arg(name)
+        .arg("--cold-start-dir")
+        .arg(dir)
+        .arg("--idle")
+        .arg(args.idle.to_string())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        bail!("child process failed: {}", output.status);
+    }
+
+    // The sample is the last line of output; anything before it was written by the guest
+    let stdout = String::from_utf8(output.stdout)?;
+    let line = stdout
+        .lines()
+        .last()
+        .ok_or_else(|| anyhow!("child process produced no output"))?;
+
+    Ok(serde_json::from_str(line)?)
+}
+
+/// Run the selected cold-start scenarios, returning the names of any which failed
+fn cold_start(args: &Args) -> Result<Vec<String>> {
+    let Some(scenarios) = select(args, COLD_START_SCENARIOS) else {
+        return Ok(Vec::new());
+    };
+
+    let dir = tempfile::tempdir()?;
+    ColdStartScenario::prepare(dir.path())?;
+
+    drive(args, &scenarios, |scenario, report| {
+        let samples = (0..args.samples)
+            .map(|_| cold_start_sample(args, scenario.name, dir.path()))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (phase, summary) in summarize(&samples) {
+            report.push(
+                format!(
+                    "{phase:>13}: {:>12.0} ns (p50 {} p90 {} max {}, n = {})",
+                    summary.mean_ns,
+                    summary.p50_ns,
+                    summary.p90_ns,
+                    summary.max_ns,
+                    summary.iterations
+                ),
+                PhaseRecord {
+                    scenario: scenario.name,
+                    phase,
+                    summary,
+                },
+            );
+        }
+
+        Ok(())
+    })
+}
+
+fn memory_child(args: &Args, name: &str) -> Result<()> {
+    let scenario = MEMORY_SCENARIOS
+        .iter()
+        .find(|scenario| scenario.name == name)
+        .ok_or_else(|| anyhow!("unknown memory scenario: {name}"))?;
+
+    let footprint = scenario.measure(args.instances)?;
+
+    println!("{}", serde_json::to_string(&footprint)?);
+
+    Ok(())
+}
+
+/// Measure the memory footprint of the named scenario in a new process
+fn memory_footprint(args: &Args, name: &str) -> Result<Footprint> {
+    let output = Command::new(env::current_exe()?)
+        .arg("--memory-child")
+        .arg(name)
+        .arg("--instances")
+        .arg(args.instances.to_string())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        bail!("child process failed: {}", output.status);
+    }
+
+    // The footprint is the last line of output; anything before it was written by the guest
+    let stdout = String::from_utf8(output.stdout)?;
+    let line = stdout
+        .lines()
+        .last()
+        .ok_or_else(|| anyhow!("child process produced no output"))?;
+
+    Ok(serde_json::from_str(line)?)
+}
+
+/// Run the selected memory scenarios, returning the names of any which failed
+fn memory(args: &Args) -> Result<Vec<String>> {
+    let Some(scenarios) = select(args, MEMORY_SCENARIOS) else {
+        return Ok(Vec::new());
+    };
+
+    if args.instances == 0 {
+        bail!("instance count must be at least one");
+    }
+
+    MemoryScenario::prepare();
+
+    drive(args, &scenarios, |scenario, report| {
+        let footprint = memory_footprint(args, scenario.name)?;
+
+        report.push(
+            format!(
+                "per instance: rss {:>8.1} KiB pss {:>8.1} KiB committed {:>10.1} KiB; \
+                 per request: {:.1} minor / {:.1} major faults; setup rss {} KiB (n = {})",
+                footprint.rss_bytes_per_instance / 1024.0,
+                footprint.pss_bytes_per_instance / 1024.0,
+                footprint.committed_bytes_per_instance / 1024.0,
+                footprint.minor_faults_per_request,
+                footprint.major_faults_per_request,
+                footprint.setup.rss_bytes / 1024,
+                footprint.instances
+            ),
+            MemoryRecord {
+                scenario: scenario.name,
+                footprint,
+            },
+        );
+
+        Ok(())
+    })
+}
+
+/// Run the selected sweep scenarios at each selected point, returning the names of any points which failed
+fn sweep(args: &Args, options: Options) -> Result<Vec<String>> {
+    let points = SWEEP_SCENARIOS.iter().flat_map(|scenario| {
+        DIMENSIONS.iter().flat_map(move |&dimension| {
+            dimension.values().iter().map(move |&value| SweepPoint {
+                scenario,
+                dimension,
+                value,
+                name: format!("{}/{}/{value}", scenario.name, dimension.name()),
+            })
+        })
+    });
+
+    let Some(points) = select(args, points) else {
+        return Ok(Vec::new());
+    };
+
+    drive(args, &points, |point, report| {
+        let mut bencher = Bencher::new(options);
+        point
+            .scenario
+            .run(point.dimension, point.value, &mut bencher)?;
+        let summary = bencher
+            .summary()
+            .ok_or_else(|| anyhow!("scenario did not call `Bencher::iter`"))?;
+
+        report.push(
+            format!(
+                "{:>12.0} ns/iter (+/- {:.0}) p50 {} p99 {} (n = {})",
+                summary.mean_ns,
+                summary.stddev_ns,
+                summary.p50_ns,
+                summary.p99_ns,
+                summary.iterations
+            ),
+            SweepRecord {
+                scenario: point.scenario.name,
+                dimension: point.dimension.name(),
+                value: point.value,
+                summary,
+            },
+        );
+
+        Ok(())
+    })
+}
+
+/// Run the selected phase scenarios, returning the names of any which failed
+fn phases(args: &Args, options: Options) -> Result<Vec<String>> {
+    let Some(scenarios) = select(args, PHASE_SCENARIOS) else {
+        return Ok(Vec::new());
+    };
+
+    drive(args, &scenarios, |scenario, report| {
+        let summaries = scenario.measure(options)?.summarize();
+        let total = summaries
+            .iter()
+            .find(|(phase, _)| *phase == Phase::Total)
+            .map_or(0.0, |(_, summary)| summary.mean_ns);
+
+        for (phase, summary) in summaries {
+            report.push(
+                format!(
+                    "{:>11}: {:>12.0} ns ({:>5.1}%) p50 {} p99 {} max {} (n = {})",
+                    phase.name(),
+                    summary.mean_ns,
+                    summary.mean_ns * 100.0 / total,
+                    summary.p50_ns,
+                    summary.p99_ns,
+                    summary.max_ns,
+                    summary.iterations
+                ),
+                PhaseRecord {
+                    scenario: scenario.name,
+                    phase: phase.name(),
+                    summary,
+                },
+            );
+        }
+
+        Ok(())
+    })
+}
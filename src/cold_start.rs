@@ -0,0 +1,280 @@
+//! Cold-start measurement: the first request handled by a freshly started process, broken down by phase, plus a
+//! request which arrives after the process has been idle
+//!
+//! `ColdStartScenario::measure` is only meaningful as the first thing a process does, so the runner starts a new
+//! child process for each sample.
+
+use {
+    super::{
+        bencher::{new_histogram, Summary},
+        compile_guests,
+        front_end::Strategy,
+        read_component, spin_engine, spin_test_instance, stdio_host, Host,
+    },
+    anyhow::{Error, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        fs, hint,
+        path::Path,
+        time::{Duration, Instant},
+    },
+    tokio::{runtime::Runtime, time},
+    wasmtime::{
+        component::{
+            Component, Instance as ComponentInstance, InstancePre as ComponentInstancePre,
+            Linker as ComponentLinker,
+        },
+        Engine, Store,
+    },
+};
+
+/// The guest every cold-start scenario runs, relative to the build output directory
+const WASM_PATH: &str = "/wasm32-wasi/release/spin_guest.wasm";
+
+/// Name of the precompiled component `ColdStartScenario::prepare` writes
+const CWASM: &str = "spin_guest.cwasm";
+
+/// Number of requests to send between the first request and the idle period, so that the request after the idle
+/// period isn't also a cold one by virtue of being only the second request
+const WARM_REQUESTS: usize = 100;
+
+/// Amount of memory to touch after the idle period in order to evict the CPU caches
+const EVICT_BYTES: usize = 64 << 20;
+
+/// How a process gets an instance to handle each request
+#[derive(Copy, Clone, Debug)]
+pub enum Start {
+    /// Compile and instantiate the component from scratch for each request
+    Fresh,
+
+    /// Deserialize a precompiled component and instantiate it for each request
+    PreCompile,
+
+    /// Instantiate a pre-compiled, pre-linked `InstancePre` for each request
+    PreInstance,
+
+    /// As above, but using the pooling allocator
+    Pooling,
+
+    /// Handle every request with the instance created for the first request
+    Reuse,
+}
+
+impl Start {
+    /// The strategy whose engine configuration this uses
+    fn strategy(self) -> Strategy {
+        match self {
+            Self::Fresh | Self::PreCompile => Strategy::Fresh,
+            Self::PreInstance => Strategy::PreInstance,
+            Self::Pooling => Strategy::Pooling,
+            Self::Reuse => Strategy::Reuse,
+        }
+    }
+}
+
+pub struct ColdStartScenario {
+    pub name: &'static str,
+    pub start: Start,
+}
+
+/// All cold-start scenarios, in the order the runner executes them
+pub const COLD_START_SCENARIOS: &[ColdStartScenario] = &[
+    ColdStartScenario {
+        name: "spin_rust_cold_start",
+        start: Start::Fresh,
+    },
+    ColdStartScenario {
+        name: "spin_rust_cold_start_pre_compile",
+        start: Start::PreCompile,
+    },
+    ColdStartScenario {
+        name: "spin_rust_cold_start_pre_instance",
+        start: Start::PreInstance,
+    },
+    ColdStartScenario {
+        name: "spin_rust_cold_start_pre_instance_with_pooling",
+        start: Start::Pooling,
+    },
+    ColdStartScenario {
+        name: "spin_rust_cold_start_reuse_instance",
+        start: Start::Reuse,
+    },
+];
+
+/// Timings from a single process, in nanoseconds
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Sample {
+    /// Creating the `Engine` and `Linker`
+    pub engine_ns: u64,
+    /// Componentizing and compiling the component, or deserializing it if precompiled
+    pub load_ns: u64,
+    /// Creating an `InstancePre`, for strategies which use one
+    pub link_ns: u64,
+    /// Instantiating the component for the first time
+    pub instantiate_ns: u64,
+    /// Calling `handle-request` for the first time
+    pub call_ns: u64,
+    /// The sum of all the above, i.e. the latency of the first request after process start
+    pub first_request_ns: u64,
+    /// The latency of a request which arrives after the process has been idle
+    pub after_idle_ns: u64,
+}
+
+/// Return the time elapsed since `since` in nanoseconds, and reset `since` to now
+fn lap(since: &mut Instant) -> u64 {
+    let now = Instant::now();
+    let nanos = now
+        .duration_since(*since)
+        .as_nanos()
+        .try_into()
+        .unwrap_or(u64::MAX);
+    *since = now;
+    nanos
+}
+
+struct Loaded<'a> {
+    start: Start,
+    dir: &'a Path,
+    engine: Engine,
+    linker: ComponentLinker<Host>,
+    pre: Option<ComponentInstancePre<Host>>,
+}
+
+impl Loaded<'_> {
+    fn load(&self) -> Result<Component> {
+        if let Start::PreCompile = self.start {
+            unsafe { Component::deserialize_file(&self.engine, self.dir.join(CWASM)) }
+        } else {
+            Component::new(&self.engine, read_component(WASM_PATH)?)
+        }
+    }
+
+    async fn instantiate(
+        &self,
+        store: &mut Store<Host>,
+        component: Option<&Component>,
+    ) -> Result<ComponentInstance> {
+        match (&self.pre, component) {
+            (Some(pre), _) => pre.instantiate_async(store).await,
+            (None, Some(component)) => self.linker.instantiate_async(store, component).await,
+            (None, None) => self.linker.instantiate_async(store, &self.load()?).await,
+        }
+    }
+
+    /// Handle a request the way this strategy normally would once warmed up
+    async fn request(&self, reused: &mut (Store<Host>, ComponentInstance)) -> Result<()> {
+        if let Start::Reuse = self.start {
+            let (store, instance) = reused;
+            spin_test_instance(store, instance).await
+        } else {
+            let mut store = Store::new(&self.engine, stdio_host());
+            let instance = self.instantiate(&mut store, None).await?;
+            spin_test_instance(&mut store, &instance).await
+        }
+    }
+}
+
+impl ColdStartScenario {
+    /// Build the guest and write any artifacts `measure` needs (e.g. a precompiled component) to `dir`
+    ///
+    /// This should be called once by the parent process, before starting any child processes.
+    pub fn prepare(dir: &Path) -> Result<()> {
+        compile_guests();
+
+        let (engine, _) = spin_engine(Start::PreCompile.strategy().config())?;
+        fs::write(
+            dir.join(CWASM),
+            engine.precompile_component(&read_component(WASM_PATH)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Measure the first request handled by this process, then measure another after sleeping for `idle` and
+    /// evicting the CPU caches
+    pub fn measure(&self, dir: &Path, idle: Duration) -> Result<Sample> {
+        let runtime = Runtime::new()?;
+
+        runtime.block_on(async {
+            let mut since = Instant::now();
+
+            let (engine, linker) = spin_engine(self.start.strategy().config())?;
+            let engine_ns = lap(&mut since);
+
+            let mut loaded = Loaded {
+                start: self.start,
+                dir,
+                engine,
+                linker,
+                pre: None,
+            };
+
+            let component = loaded.load()?;
+            let load_ns = lap(&mut since);
+
+            if let Start::PreInstance | Start::Pooling | Start::Reuse = self.start {
+                loaded.pre = Some(loaded.linker.instantiate_pre(&component)?);
+            }
+            let link_ns = lap(&mut since);
+
+            let mut store = Store::new(&loaded.engine, stdio_host());
+            let instance = loaded.instantiate(&mut store, Some(&component)).await?;
+            let instantiate_ns = lap(&mut since);
+
+            spin_test_instance(&mut store, &instance).await?;
+            let call_ns = lap(&mut since);
+
+            let mut reused = (store, instance);
+            for _ in 0..WARM_REQUESTS {
+                loaded.request(&mut reused).await?;
+            }
+
+            time::sleep(idle).await;
+
+            let mut buffer = vec![0_u8; EVICT_BYTES];
+            for line in buffer.chunks_mut(64) {
+                line[0] = line[0].wrapping_add(1);
+            }
+            hint::black_box(&buffer);
+            drop(buffer);
+
+            let mut since = Instant::now();
+            loaded.request(&mut reused).await?;
+            let after_idle_ns = lap(&mut since);
+
+            Ok::<_, Error>(Sample {
+                engine_ns,
+                load_ns,
+                link_ns,
+                instantiate_ns,
+                call_ns,
+                first_request_ns: engine_ns + load_ns + link_ns + instantiate_ns + call_ns,
+                after_idle_ns,
+            })
+        })
+    }
+}
+
+/// Summarize each phase across samples, e.g. from several child processes
+pub fn summarize(samples: &[Sample]) -> Vec<(&'static str, Summary)> {
+    let phases: [(&str, fn(&Sample) -> u64); 7] = [
+        ("engine", |sample| sample.engine_ns),
+        ("load", |sample| sample.load_ns),
+        ("link", |sample| sample.link_ns),
+        ("instantiate", |sample| sample.instantiate_ns),
+        ("call", |sample| sample.call_ns),
+        ("first_request", |sample| sample.first_request_ns),
+        ("after_idle", |sample| sample.after_idle_ns),
+    ];
+
+    phases
+        .into_iter()
+        .map(|(phase, get)| {
+            let mut histogram = new_histogram();
+            for sample in samples {
+                histogram.saturating_record(get(sample));
+            }
+            (phase, Summary::from_histogram(&histogram))
+        })
+        .collect()
+}
@@ -0,0 +1,458 @@
+//! HTTP/1.1 front-end which dispatches real requests to a Spin component's `inbound-http.handle-request` export
+//!
+//! This lets us measure end-to-end latency, including HTTP parsing and socket I/O, for each instantiation
+//! strategy.
+
+use {
+    super::{
+        phases::{Phase, Timer},
+        read_component, spin_engine, Host, Method, RequestParam, Response,
+    },
+    anyhow::{anyhow, bail, Result},
+    hyper::{
+        body,
+        header::{CONNECTION, CONTENT_LENGTH, HOST, TRANSFER_ENCODING},
+        service::{make_service_fn, service_fn},
+        Body, Server, StatusCode,
+    },
+    serde::Deserialize,
+    std::{convert::Infallible, net::SocketAddr, sync::Arc},
+    tokio::{sync::Mutex, task::JoinHandle},
+    wasmtime::{
+        component::{
+            Component, Func as ComponentFunc, Instance as ComponentInstance,
+            InstancePre as ComponentInstancePre, Linker as ComponentLinker,
+        },
+        Config, Engine, InstanceAllocationStrategy, PoolingAllocationConfig, Store,
+    },
+};
+
+/// How a new request gets an instance to handle it
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Compile and instantiate the component from scratch for each request
+    Fresh,
+
+    /// Instantiate a pre-compiled, pre-linked `InstancePre` for each request
+    #[default]
+    PreInstance,
+
+    /// As above, but using the pooling allocator
+    Pooling,
+
+    /// Handle every request with a single, long-lived instance
+    Reuse,
+}
+
+impl Strategy {
+    pub fn config(self) -> Config {
+        let mut config = Config::new();
+        if let Self::Pooling = self {
+            config.allocation_strategy(InstanceAllocationStrategy::Pooling(
+                PoolingAllocationConfig::default(),
+            ));
+        }
+        config
+    }
+}
+
+/// An incoming request, converted from HTTP but not yet lowered into a guest
+pub struct IncomingRequest {
+    pub method: Method,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+enum Instances {
+    Fresh {
+        engine: Engine,
+        linker: ComponentLinker<Host>,
+        component: Vec<u8>,
+    },
+    Pre {
+        engine: Engine,
+        pre: ComponentInstancePre<Host>,
+    },
+    Reuse(Mutex<(Store<Host>, ComponentFunc)>),
+}
+
+pub fn handle_request_func(
+    store: &mut Store<Host>,
+    instance: &ComponentInstance,
+) -> Result<ComponentFunc> {
+    instance
+        .exports(&mut *store)
+        .instance("inbound-http")
+        .ok_or_else(|| anyhow!("no inbound-http instance found"))?
+        .func("handle-request")
+        .ok_or_else(|| anyhow!("no handle-request function found"))
+}
+
+/// Call `func` (i.e. `handle-request`) with the specified request and route `params`, telling `timer` when the call
+/// and `post_return` are complete
+pub async fn call(
+    store: &mut Store<Host>,
+    func: ComponentFunc,
+    request: &IncomingRequest,
+    params: &[(&str, &str)],
+    timer: &mut impl Timer,
+) -> Result<Response> {
+    let func = func.typed::<(RequestParam,), (Response,), _>(&*store)?;
+
+    let headers = request
+        .headers
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+
+    let (response,) = func
+        .call_async(
+            &mut *store,
+            (RequestParam {
+                method: request.method,
+                uri: &request.uri,
+                headers: &headers,
+                params,
+                body: request.body.as_deref(),
+            },),
+        )
+        .await?;
+    timer.lap(Phase::Call);
+
+    func.post_return_async(&mut *store).await?;
+    timer.lap(Phase::PostReturn);
+
+    Ok(response)
+}
+
+/// A Spin component, ready to handle requests according to a `Strategy`
+pub struct Dispatcher {
+    instances: Instances,
+    make_host: Box<dyn Fn() -> Host + Send + Sync>,
+}
+
+impl Dispatcher {
+    /// Create a dispatcher for the guest module at `wasm_path` (relative to the build output directory)
+    pub async fn new(
+        wasm_path: &str,
+        strategy: Strategy,
+        make_host: impl Fn() -> Host + Send + Sync + 'static,
+    ) -> Result<Self> {
+        Self::from_component(
+            read_component(wasm_path)?,
+            strategy,
+            strategy.config(),
+            make_host,
+        )
+        .await
+    }
+
+    /// Create a dispatcher for an already-componentized guest, using `config` (which should already reflect
+    /// `strategy`, e.g. by specifying the pooling allocator) to create the engine
+    pub async fn from_component(
+        component: Vec<u8>,
+        strategy: Strategy,
+        config: Config,
+        make_host: impl Fn() -> Host + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let (engine, linker) = spin_engine(config)?;
+
+        let instances = match strategy {
+            Strategy::Fresh => Instances::Fresh {
+                engine,
+                linker,
+                component,
+            },
+
+            Strategy::PreInstance | Strategy::Pooling => {
+                let pre = linker.instantiate_pre(&Component::new(&engine, component)?)?;
+                Instances::Pre { engine, pre }
+            }
+
+            Strategy::Reuse => {
+                let pre = linker.instantiate_pre(&Component::new(&engine, component)?)?;
+                let mut store = Store::new(&engine, make_host());
+                let instance = pre.instantiate_async(&mut store).await?;
+                let func = handle_request_func(&mut store, &instance)?;
+                Instances::Reuse(Mutex::new((store, func)))
+            }
+        };
+
+        Ok(Self {
+            instances,
+            make_host: Box::new(make_host),
+        })
+    }
+
+    /// Create a new store and instance, as `dispatch` does for each request unless the strategy is
+    /// `Strategy::Reuse`, in which case this fails
+    pub async fn instantiate(&self) -> Result<(Store<Host>, ComponentInstance)> {
+        self.instantiate_with(&mut ()).await
+    }
+
+    /// Like `instantiate`, but telling `timer` when each phase is complete
+    pub async fn instantiate_with(
+        &self,
+        timer: &mut impl Timer,
+    ) -> Result<(Store<Host>, ComponentInstance)> {
+        let (Instances::Fresh { engine, .. } | Instances::Pre { engine, .. }) = &self.instances
+        else {
+            bail!("a dispatcher which reuses its instance can't create more");
+        };
+
+        let host = (self.make_host)();
+        timer.lap(Phase::Host);
+        let mut store = Store::new(engine, host);
+        timer.lap(Phase::Store);
+
+        let instance = match &self.instances {
+            Instances::Fresh {
+                linker, component, ..
+            } => {
+                let component = Component::new(engine, component)?;
+                timer.lap(Phase::Compile);
+                linker.instantiate_async(&mut store, &component).await?
+            }
+            Instances::Pre { pre, .. } => pre.instantiate_async(&mut store).await?,
+            Instances::Reuse(_) => unreachable!(),
+        };
+        timer.lap(Phase::Instantiate);
+
+        Ok((store, instance))
+    }
+
+    pub async fn dispatch(&self, request: &IncomingRequest) -> Result<Response> {
+        self.dispatch_with(request, &mut ()).await
+    }
+
+    /// Like `dispatch`, but telling `timer` when each phase of handling the request is complete
+    pub async fn dispatch_with(
+        &self,
+        request: &IncomingRequest,
+        timer: &mut impl Timer,
+    ) -> Result<Response> {
+        if let Instances::Reuse(instance) = &self.instances {
+            let (store, func) = &mut *instance.lock().await;
+            timer.lap(Phase::Lock);
+            return call(store, *func, request, &[], timer).await;
+        }
+
+        let (mut store, instance) = self.instantiate_with(timer).await?;
+        let func = handle_request_func(&mut store, &instance)?;
+        timer.lap(Phase::Lookup);
+        let response = call(&mut store, func, request, &[], timer).await?;
+        drop(store);
+        timer.lap(Phase::Drop);
+
+        Ok(response)
+    }
+}
+
+/// Convert an HTTP request to an `IncomingRequest`, or return the status code to reject it with
+///
+/// Headers which only describe the HTTP/1.1 connection or message framing (e.g. `host` and `content-length`) are
+/// not passed to the guest, since the guest sees the URI and body directly.
+async fn convert_request(
+    request: hyper::Request<Body>,
+) -> Result<Result<IncomingRequest, StatusCode>> {
+    let method = match *request.method() {
+        hyper::Method::GET => Method::Get,
+        hyper::Method::POST => Method::Post,
+        hyper::Method::PUT => Method::Put,
+        hyper::Method::DELETE => Method::Delete,
+        hyper::Method::PATCH => Method::Patch,
+        hyper::Method::HEAD => Method::Head,
+        hyper::Method::OPTIONS => Method::Options,
+        _ => return Ok(Err(StatusCode::METHOD_NOT_ALLOWED)),
+    };
+
+    let uri = request
+        .uri()
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/")
+        .to_owned();
+
+    let mut headers = Vec::with_capacity(request.headers().len());
+    for (name, value) in request.headers() {
+        if [HOST, CONTENT_LENGTH, TRANSFER_ENCODING, CONNECTION].contains(name) {
+            continue;
+        }
+
+        let Ok(value) = value.to_str() else {
+            return Ok(Err(StatusCode::BAD_REQUEST));
+        };
+
+        headers.push((name.as_str().to_owned(), value.to_owned()));
+    }
+
+    let body = body::to_bytes(request.into_body()).await?;
+
+    Ok(Ok(IncomingRequest {
+        method,
+        uri,
+        headers,
+        body: (!body.is_empty()).then(|| body.to_vec()),
+    }))
+}
+
+fn convert_response(response: Response) -> Result<hyper::Response<Body>> {
+    let mut builder = hyper::Response::builder().status(response.status);
+    for (name, value) in response.headers.unwrap_or_default() {
+        builder = builder.header(name, value);
+    }
+    Ok(builder.body(response.body.unwrap_or_default().into())?)
+}
+
+async fn serve(dispatcher: &Dispatcher, request: hyper::Request<Body>) -> hyper::Response<Body> {
+    let result = async {
+        match convert_request(request).await? {
+            Ok(request) => convert_response(dispatcher.dispatch(&request).await?),
+            Err(status) => Ok(hyper::Response::builder()
+                .status(status)
+                .body(Body::empty())?),
+        }
+    }
+    .await;
+
+    result.unwrap_or_else(|e| {
+        let mut response = hyper::Response::new(Body::from(format!("{e:?}")));
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    })
+}
+
+/// HTTP/1.1 server which hands each request to a `Dispatcher`
+///
+/// The server runs as a task on the current Tokio runtime and is stopped when this handle is dropped.
+pub struct FrontEnd {
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl FrontEnd {
+    pub fn start(dispatcher: Dispatcher) -> Result<Self> {
+        let dispatcher = Arc::new(dispatcher);
+
+        let server =
+            Server::try_bind(&([127, 0, 0, 1], 0).into())?.serve(make_service_fn(move |_| {
+                let dispatcher = dispatcher.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let dispatcher = dispatcher.clone();
+                        async move { Ok::<_, Infallible>(serve(&dispatcher, request).await) }
+                    }))
+                }
+            }));
+
+        let address = server.local_addr();
+
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("front-end server error: {e:?}");
+            }
+        });
+
+        Ok(Self { address, task })
+    }
+
+    pub fn url(&self, path_and_query: &str) -> String {
+        format!("http://{}{path_and_query}", self.address)
+    }
+}
+
+impl Drop for FrontEnd {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[test]
+fn request_conversion() -> Result<()> {
+    use hyper::header::HeaderValue;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    let request = hyper::Request::builder()
+        .method(hyper::Method::TRACE)
+        .uri("/foo")
+        .body(Body::empty())?;
+    assert!(matches!(
+        runtime.block_on(convert_request(request))?,
+        Err(StatusCode::METHOD_NOT_ALLOWED)
+    ));
+
+    let request = hyper::Request::builder()
+        .uri("/foo")
+        .header("what", HeaderValue::from_bytes(b"\xffup")?)
+        .body(Body::empty())?;
+    assert!(matches!(
+        runtime.block_on(convert_request(request))?,
+        Err(StatusCode::BAD_REQUEST)
+    ));
+
+    let request = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri("/foo?a=b")
+        .header(HOST, "localhost")
+        .header(CONTENT_LENGTH, "13")
+        .header(TRANSFER_ENCODING, "identity")
+        .header(CONNECTION, "keep-alive")
+        .header("what", "up")
+        .body(Body::from("hello, world!"))?;
+    let Ok(request) = runtime.block_on(convert_request(request))? else {
+        panic!("request should have been accepted");
+    };
+    assert!(matches!(request.method, Method::Post));
+    assert_eq!("/foo?a=b", request.uri);
+    assert_eq!(vec![("what".to_owned(), "up".to_owned())], request.headers);
+    assert_eq!(Some(b"hello, world!" as &[_]), request.body.as_deref());
+
+    let request = hyper::Request::builder().uri("/").body(Body::empty())?;
+    let Ok(request) = runtime.block_on(convert_request(request))? else {
+        panic!("request should have been accepted");
+    };
+    assert!(matches!(request.method, Method::Get));
+    assert!(request.body.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn front_end_loopback() -> Result<()> {
+    super::compile_guests();
+
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    runtime.block_on(async {
+        let front_end = FrontEnd::start(
+            Dispatcher::new(
+                "/wasm32-wasi/release/spin_guest.wasm",
+                Strategy::PreInstance,
+                super::stdio_host,
+            )
+            .await?,
+        )?;
+
+        // The guest asserts that it sees exactly the method, URI, `what` header, and body sent here, so this also
+        // checks that connection and framing headers (e.g. `host` and `content-length`) are not passed through
+        let response = hyper::Client::new()
+            .request(
+                hyper::Request::post(front_end.url("/foo?a=b"))
+                    .header("what", "up")
+                    .body(Body::from("hello, world!"))?,
+            )
+            .await?;
+
+        assert_eq!(200, response.status());
+        assert_eq!("text/plain", response.headers()["content-type"]);
+        assert_eq!(
+            b"hola, mundo!" as &[_],
+            &body::to_bytes(response.into_body()).await?
+        );
+
+        Ok(())
+    })
+}
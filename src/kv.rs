@@ -0,0 +1,447 @@
+//! In-memory and SQLite backends for the `key-value` interface
+
+use {
+    super::{key_value, Host},
+    anyhow::Result,
+    async_trait::async_trait,
+    rusqlite::{Connection, OptionalExtension},
+    std::{
+        collections::{HashMap, HashSet},
+        ops::Deref,
+        path::Path,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Default maximum number of stores a single instance may have open at once
+const DEFAULT_MAX_OPEN_STORES: usize = 16;
+
+/// A named store which may be opened by a guest
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    fn set(&self, key: String, value: Vec<u8>) -> Result<()>;
+
+    fn delete(&self, key: &str) -> Result<()>;
+
+    fn exists(&self, key: &str) -> Result<bool>;
+
+    fn get_keys(&self) -> Result<Vec<String>>;
+}
+
+/// A store which lives only as long as the `KeyValueConfig` which defines it
+pub type MemoryStore = Mutex<HashMap<String, Vec<u8>>>;
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: String, value: Vec<u8>) -> Result<()> {
+        self.lock().unwrap().insert(key, value);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.lock().unwrap().contains_key(key))
+    }
+
+    fn get_keys(&self) -> Result<Vec<String>> {
+        Ok(self.lock().unwrap().keys().cloned().collect())
+    }
+}
+
+/// A store persisted to a SQLite database file
+pub struct SqliteStore(Mutex<Connection>);
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS spin_key_value (key TEXT PRIMARY KEY, value BLOB NOT NULL)",
+            [],
+        )?;
+        Ok(Self(Mutex::new(connection)))
+    }
+}
+
+impl KeyValueStore for SqliteStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap()
+            .prepare_cached("SELECT value FROM spin_key_value WHERE key = ?1")?
+            .query_row([key], |row| row.get(0))
+            .optional()?)
+    }
+
+    fn set(&self, key: String, value: Vec<u8>) -> Result<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .prepare_cached(
+                "INSERT INTO spin_key_value (key, value) VALUES (?1, ?2) \
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            )?
+            .execute(rusqlite::params![key, value])?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .prepare_cached("DELETE FROM spin_key_value WHERE key = ?1")?
+            .execute([key])?;
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn get_keys(&self) -> Result<Vec<String>> {
+        let connection = self.0.lock().unwrap();
+        let mut statement = connection.prepare_cached("SELECT key FROM spin_key_value")?;
+        let keys = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(keys)
+    }
+}
+
+/// Host-wide key-value configuration, shared by every `Store<Host>` created from it
+pub struct KeyValueConfig {
+    stores: HashMap<String, Arc<dyn KeyValueStore>>,
+    allowed: Option<HashSet<String>>,
+    max_open_stores: usize,
+}
+
+impl Default for KeyValueConfig {
+    fn default() -> Self {
+        Self {
+            stores: HashMap::new(),
+            allowed: None,
+            max_open_stores: DEFAULT_MAX_OPEN_STORES,
+        }
+    }
+}
+
+impl KeyValueConfig {
+    /// Create a configuration with a single, empty store named "default"
+    pub fn with_default_store() -> Self {
+        Self::default().store("default")
+    }
+
+    /// Define an empty, in-memory store with the specified name
+    pub fn store(mut self, name: &str) -> Self {
+        self.stores
+            .insert(name.to_owned(), Arc::new(MemoryStore::default()));
+        self
+    }
+
+    /// Define a store with the specified name, backed by the specified SQLite database file
+    ///
+    /// The file is created if it does not already exist; otherwise, its existing contents are made available to
+    /// the guest.
+    pub fn sqlite_store(mut self, name: &str, path: &Path) -> Result<Self> {
+        self.stores
+            .insert(name.to_owned(), Arc::new(SqliteStore::open(path)?));
+        Ok(self)
+    }
+
+    /// Restrict the guest to the specified store names
+    ///
+    /// By default, the guest may open any defined store.
+    pub fn allow(mut self, names: &[&str]) -> Self {
+        self.allowed = Some(names.iter().map(|&name| name.to_owned()).collect());
+        self
+    }
+
+    /// Set the maximum number of stores a single instance may have open at once
+    pub fn max_open_stores(mut self, max: usize) -> Self {
+        self.max_open_stores = max;
+        self
+    }
+}
+
+/// Per-instance key-value state: the table of open store handles
+#[derive(Default)]
+pub struct KeyValue {
+    config: Arc<KeyValueConfig>,
+    handles: HashMap<u32, Arc<dyn KeyValueStore>>,
+    next_handle: u32,
+}
+
+impl KeyValue {
+    pub fn new(config: Arc<KeyValueConfig>) -> Self {
+        Self {
+            config,
+            handles: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    fn store(&self, store: key_value::Store) -> Result<&dyn KeyValueStore, key_value::Error> {
+        self.handles
+            .get(&store)
+            .map(Arc::deref)
+            .ok_or(key_value::Error::InvalidStore)
+    }
+
+    pub fn open(&mut self, name: &str) -> Result<key_value::Store, key_value::Error> {
+        if let Some(allowed) = &self.config.allowed {
+            if !allowed.contains(name) {
+                return Err(key_value::Error::AccessDenied);
+            }
+        }
+
+        let store = self
+            .config
+            .stores
+            .get(name)
+            .ok_or(key_value::Error::NoSuchStore)?
+            .clone();
+
+        if self.handles.len() >= self.config.max_open_stores {
+            return Err(key_value::Error::StoreTableFull);
+        }
+
+        while self.handles.contains_key(&self.next_handle) {
+            self.next_handle = self.next_handle.wrapping_add(1);
+        }
+
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        self.handles.insert(handle, store);
+
+        Ok(handle)
+    }
+
+    pub fn get(&self, store: key_value::Store, key: &str) -> Result<Vec<u8>, key_value::Error> {
+        self.store(store)?
+            .get(key)
+            .map_err(io_error)?
+            .ok_or(key_value::Error::NoSuchKey)
+    }
+
+    pub fn set(
+        &self,
+        store: key_value::Store,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<(), key_value::Error> {
+        self.store(store)?.set(key, value).map_err(io_error)
+    }
+
+    pub fn delete(&self, store: key_value::Store, key: &str) -> Result<(), key_value::Error> {
+        self.store(store)?.delete(key).map_err(io_error)
+    }
+
+    pub fn exists(&self, store: key_value::Store, key: &str) -> Result<bool, key_value::Error> {
+        self.store(store)?.exists(key).map_err(io_error)
+    }
+
+    pub fn get_keys(&self, store: key_value::Store) -> Result<Vec<String>, key_value::Error> {
+        self.store(store)?.get_keys().map_err(io_error)
+    }
+
+    pub fn close(&mut self, store: key_value::Store) {
+        self.handles.remove(&store);
+    }
+}
+
+fn io_error(error: anyhow::Error) -> key_value::Error {
+    key_value::Error::Io(format!("{error:?}"))
+}
+
+#[async_trait]
+impl key_value::Host for Host {
+    async fn open(&mut self, name: String) -> Result<Result<key_value::Store, key_value::Error>> {
+        Ok(self.key_value.open(&name))
+    }
+
+    async fn get(
+        &mut self,
+        store: key_value::Store,
+        key: String,
+    ) -> Result<Result<Vec<u8>, key_value::Error>> {
+        Ok(self.key_value.get(store, &key))
+    }
+
+    async fn set(
+        &mut self,
+        store: key_value::Store,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<Result<(), key_value::Error>> {
+        Ok(self.key_value.set(store, key, value))
+    }
+
+    async fn delete(
+        &mut self,
+        store: key_value::Store,
+        key: String,
+    ) -> Result<Result<(), key_value::Error>> {
+        Ok(self.key_value.delete(store, &key))
+    }
+
+    async fn exists(
+        &mut self,
+        store: key_value::Store,
+        key: String,
+    ) -> Result<Result<bool, key_value::Error>> {
+        Ok(self.key_value.exists(store, &key))
+    }
+
+    async fn get_keys(
+        &mut self,
+        store: key_value::Store,
+    ) -> Result<Result<Vec<String>, key_value::Error>> {
+        Ok(self.key_value.get_keys(store))
+    }
+
+    async fn close(&mut self, store: key_value::Store) -> Result<()> {
+        self.key_value.close(store);
+        Ok(())
+    }
+}
+
+#[test]
+fn key_value_errors() {
+    let mut kv = KeyValue::new(Arc::new(
+        KeyValueConfig::with_default_store()
+            .store("secret")
+            .allow(&["default", "missing"])
+            .max_open_stores(1),
+    ));
+
+    assert!(matches!(
+        kv.open("secret"),
+        Err(key_value::Error::AccessDenied)
+    ));
+    assert!(matches!(
+        kv.open("missing"),
+        Err(key_value::Error::NoSuchStore)
+    ));
+
+    let store = kv.open("default").unwrap();
+    assert!(matches!(
+        kv.open("default"),
+        Err(key_value::Error::StoreTableFull)
+    ));
+    assert!(matches!(
+        kv.get(store, "foo"),
+        Err(key_value::Error::NoSuchKey)
+    ));
+
+    kv.set(store, "foo".to_owned(), b"bar".to_vec()).unwrap();
+    assert_eq!(b"bar" as &[_], &kv.get(store, "foo").unwrap());
+    assert_eq!(vec!["foo".to_owned()], kv.get_keys(store).unwrap());
+
+    kv.delete(store, "foo").unwrap();
+    assert!(!kv.exists(store, "foo").unwrap());
+
+    kv.close(store);
+    assert!(matches!(
+        kv.exists(store, "foo"),
+        Err(key_value::Error::InvalidStore)
+    ));
+
+    // Data outlives the handles used to access it:
+    let store = kv.open("default").unwrap();
+    kv.set(store, "baz".to_owned(), b"qux".to_vec()).unwrap();
+    kv.close(store);
+    let store = kv.open("default").unwrap();
+    assert_eq!(b"qux" as &[_], &kv.get(store, "baz").unwrap());
+}
+
+#[test]
+fn sqlite_key_value_persists() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path().join("kv.db");
+
+    {
+        let mut kv = KeyValue::new(Arc::new(
+            KeyValueConfig::default().sqlite_store("default", &path)?,
+        ));
+        let store = kv.open("default").unwrap();
+        kv.set(store, "foo".to_owned(), b"bar".to_vec()).unwrap();
+        kv.set(store, "foo".to_owned(), b"baz".to_vec()).unwrap();
+        kv.set(store, "gone".to_owned(), b"soon".to_vec()).unwrap();
+        kv.delete(store, "gone").unwrap();
+    }
+
+    // A fresh configuration (and thus a fresh connection) should see everything written above:
+    let mut kv = KeyValue::new(Arc::new(
+        KeyValueConfig::default().sqlite_store("default", &path)?,
+    ));
+    let store = kv.open("default").unwrap();
+    assert_eq!(b"baz" as &[_], &kv.get(store, "foo").unwrap());
+    assert!(!kv.exists(store, "gone").unwrap());
+    assert!(matches!(
+        kv.get(store, "gone"),
+        Err(key_value::Error::NoSuchKey)
+    ));
+    assert_eq!(vec!["foo".to_owned()], kv.get_keys(store).unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn sqlite_key_value_survives_restart() -> Result<()> {
+    super::compile_guests();
+
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path().join("kv.db");
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    let fresh_store = || {
+        Ok::<_, anyhow::Error>(KeyValue::new(Arc::new(
+            KeyValueConfig::default().sqlite_store("default", &path)?,
+        )))
+    };
+
+    {
+        let mut kv = fresh_store()?;
+        let store = kv.open("default").unwrap();
+        kv.set(store, "seed".to_owned(), b"planted".to_vec())
+            .unwrap();
+    }
+
+    for _ in 0..2 {
+        // A new `Engine` and `Store` each time, as if the host had restarted, with only the database file in common
+        let (pre, engine) = super::spin_instance_pre(
+            "/wasm32-wasi/release/spin_kv_guest.wasm",
+            wasmtime::Config::new(),
+        )?;
+        let mut store = wasmtime::Store::new(
+            &engine,
+            Host {
+                key_value: fresh_store()?,
+                ..super::stdio_host()
+            },
+        );
+
+        runtime.block_on(async {
+            let instance = pre.instantiate_async(&mut store).await?;
+            super::spin_test_instance(&mut store, &instance).await
+        })?;
+
+        drop(store);
+        drop(engine);
+
+        let mut kv = fresh_store()?;
+        let store = kv.open("default").unwrap();
+        assert_eq!(b"planted" as &[_], &kv.get(store, "seed").unwrap());
+        assert_eq!(b"hello, world!" as &[_], &kv.get(store, "foo").unwrap());
+    }
+
+    Ok(())
+}
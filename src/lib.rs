@@ -1,651 +1,1127 @@
-#![feature(test)]
-
-extern crate test;
-
-#[cfg(test)]
-mod tests {
-    use {
-        super::*,
-        anyhow::{anyhow, Context, Error, Result},
-        async_trait::async_trait,
-        flate2::read::GzDecoder,
-        http_types::{HttpError, Method, RequestParam, RequestResult, Response},
-        redis_types::{RedisParameter, RedisResult},
-        std::{
-            env,
-            fs::{self, OpenOptions},
-            hint, io,
-            ops::Deref,
-            os::unix::fs::OpenOptionsExt,
-            path::Path,
-            process::Command,
-            sync::Once,
+//! Benchmarks for Wasmtime performance in serverless function workloads
+//!
+//! Each scenario in `SCENARIOS` measures the time it takes to pass an HTTP request to a handler and check its
+//! response, using a particular isolation strategy.  See `src/bin/runner.rs` for the command-line runner.
+
+pub mod baseline;
+pub mod bencher;
+pub mod cold_start;
+pub mod front_end;
+pub mod kv;
+pub mod manifest;
+pub mod memory;
+pub mod outbound_http;
+pub mod phases;
+pub mod rate_limit;
+pub mod rdbms;
+pub mod redis_store;
+pub mod resp;
+pub mod router;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub mod sandbox;
+pub mod scenario_file;
+pub mod size_sweep;
+pub mod throughput;
+pub mod variables;
+pub mod wagi;
+pub mod zygote;
+
+use {
+    anyhow::{anyhow, Context, Error, Result},
+    bencher::Bencher,
+    flate2::read::GzDecoder,
+    front_end::{Dispatcher, FrontEnd, IncomingRequest, Strategy},
+    http_types::{HttpError, Method, RequestParam, RequestResult, Response},
+    kv::{KeyValue, KeyValueConfig},
+    manifest::App,
+    outbound_http::{AllowedHosts, Backend, OutboundHttp},
+    rate_limit::{Limit, RateLimits},
+    rdbms::SqlEngine,
+    redis_store::{RedisBackend, RedisStore},
+    redis_types::{RedisParameter, RedisResult},
+    resp::{RespClient, RespServer},
+    std::{
+        env,
+        fs::{self, OpenOptions},
+        hint, io,
+        ops::Deref,
+        os::unix::fs::OpenOptionsExt,
+        panic::{self, AssertUnwindSafe},
+        path::{Path, PathBuf},
+        process::Command,
+        sync::{Arc, Once},
+    },
+    tar::Archive,
+    tokio::runtime::{self, Runtime},
+    variables::{ConfigResolver, EnvProvider, TomlProvider},
+    wagi::{CgiExecutor, Mount, WagiExecutor},
+    wasmtime::{
+        component::{
+            Component, Instance as ComponentInstance, InstancePre as ComponentInstancePre,
+            Linker as ComponentLinker, TypedFunc as ComponentTypedFunc,
         },
-        tar::Archive,
-        test::Bencher,
-        tokio::runtime::Runtime,
-        wasmtime::{
-            component::{
-                Component, Instance as ComponentInstance, InstancePre as ComponentInstancePre,
-                Linker as ComponentLinker, TypedFunc as ComponentTypedFunc,
-            },
-            Config, Engine, InstanceAllocationStrategy, Linker as ModuleLinker, Module,
-            PoolingAllocationConfig, Store,
-        },
-    };
-
-    wasmtime::component::bindgen!({
-        path: "wit",
-        world: "spin-http",
-        async: true
-    });
-
-    struct Host {
-        wasi: wasi_preview2::WasiCtx,
-    }
+        Config, Engine, Store,
+    },
+    zygote::Zygote,
+};
+
+wasmtime::component::bindgen!({
+    path: "wit",
+    world: "spin-http",
+    async: true
+});
+
+pub struct Host {
+    wasi: wasi_preview2::WasiCtx,
+    key_value: KeyValue,
+    redis: RedisBackend,
+    config: Arc<ConfigResolver>,
+    sql: Arc<SqlEngine>,
+    http: Arc<OutboundHttp>,
+}
 
-    #[async_trait]
-    impl config::Host for Host {
-        async fn get_config(&mut self, _key: String) -> Result<Result<String, config::Error>> {
-            unreachable!()
+impl Host {
+    fn new(wasi: wasi_preview2::WasiCtx) -> Self {
+        Self {
+            wasi,
+            key_value: KeyValue::default(),
+            redis: RedisBackend::default(),
+            config: Arc::default(),
+            sql: Arc::default(),
+            http: Arc::default(),
         }
     }
+}
 
-    #[async_trait]
-    impl redis::Host for Host {
-        async fn publish(
-            &mut self,
-            _address: String,
-            _channel: String,
-            _payload: Vec<u8>,
-        ) -> Result<Result<(), redis_types::Error>> {
-            unreachable!()
-        }
+fn add_to_linker(linker: &mut ComponentLinker<Host>) -> anyhow::Result<()> {
+    wasi_host::command::add_to_linker(linker, |host| &mut host.wasi)?;
+    config::add_to_linker(linker, |host| host)?;
+    postgres::add_to_linker(linker, |host| host)?;
+    mysql::add_to_linker(linker, |host| host)?;
+    redis::add_to_linker(linker, |host| host)?;
+    key_value::add_to_linker(linker, |host| host)?;
+    http::add_to_linker(linker, |host| host)?;
 
-        async fn get(
-            &mut self,
-            _address: String,
-            _key: String,
-        ) -> Result<Result<Vec<u8>, redis_types::Error>> {
-            unreachable!()
-        }
+    Ok(())
+}
 
-        async fn set(
-            &mut self,
-            _address: String,
-            _key: String,
-            _value: Vec<u8>,
-        ) -> Result<Result<(), redis_types::Error>> {
-            unreachable!()
-        }
+/// Resolve a path relative to this package's root, where the guest crates and manifests live, regardless of the
+/// current directory
+fn package_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
 
-        async fn incr(
-            &mut self,
-            _address: String,
-            _key: String,
-        ) -> Result<Result<i64, redis_types::Error>> {
-            unreachable!()
+fn build_python_app() -> Result<()> {
+    let out_dir = Path::new(env!("OUT_DIR"));
+    let wasm_path = out_dir.join("python-spin-guest.wasm");
+    let py2wasm_path = out_dir.join("py2wasm");
+    if !py2wasm_path.exists() {
+        let tar = Runtime::new()?.block_on(async {
+            reqwest::get(&format!(
+                "https://github.com/fermyon/spin-python-sdk/\
+                 releases/download/v0.1.1/py2wasm-v0.1.1-{}-{}.tar.gz",
+                env::consts::OS,
+                env::consts::ARCH
+            ))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await
+        })?;
+
+        let mut tar = Archive::new(GzDecoder::new(tar.deref()));
+
+        for file in tar.entries()? {
+            let mut file = file?;
+            if let Some("py2wasm") = file.path()?.to_str() {
+                io::copy(
+                    &mut file,
+                    &mut OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .mode(0o744)
+                        .open(&py2wasm_path)?,
+                )?;
+                break;
+            }
         }
+    }
 
-        async fn del(
-            &mut self,
-            _address: String,
-            _keys: Vec<String>,
-        ) -> Result<Result<i64, redis_types::Error>> {
-            unreachable!()
-        }
+    assert!(Command::new(py2wasm_path)
+        .current_dir(package_path("python-spin-guest"))
+        .arg("app")
+        .arg("-o")
+        .arg(wasm_path)
+        .status()?
+        .success());
 
-        async fn sadd(
-            &mut self,
-            _address: String,
-            _key: String,
-            _values: Vec<String>,
-        ) -> Result<Result<i64, redis_types::Error>> {
-            unreachable!()
-        }
+    Ok(())
+}
 
-        async fn smembers(
-            &mut self,
-            _address: String,
-            _key: String,
-        ) -> Result<Result<Vec<String>, redis_types::Error>> {
-            unreachable!()
+fn compile_guests() {
+    static ONCE: Once = Once::new();
+
+    let once = || {
+        for guest in [
+            "wagi-guest",
+            "wagi-echo-guest",
+            "spin-guest",
+            "spin-echo-guest",
+            "spin-kv-guest",
+            "spin-redis-guest",
+            "spin-config-guest",
+            "spin-sql-guest",
+            "spin-outbound-http-guest",
+            "spin-sdk-guest",
+        ] {
+            let mut cmd = Command::new("cargo");
+            cmd.arg("build")
+                .current_dir(package_path(guest))
+                .arg("--release")
+                .arg("--target=wasm32-wasi")
+                .env("CARGO_TARGET_DIR", env!("OUT_DIR"));
+
+            let status = cmd.status()?;
+            assert!(status.success());
         }
 
-        async fn srem(
-            &mut self,
-            _address: String,
-            _key: String,
-            _values: Vec<String>,
-        ) -> Result<Result<i64, redis_types::Error>> {
-            unreachable!()
-        }
+        // `wagi-guest` is also built for the host, to be executed natively as a classic CGI program
+        let status = Command::new("cargo")
+            .arg("build")
+            .current_dir("wagi-guest")
+            .arg("--release")
+            .env("CARGO_TARGET_DIR", env!("OUT_DIR"))
+            .status()?;
+        assert!(status.success());
 
-        async fn execute(
-            &mut self,
-            _address: String,
-            _command: String,
-            _arguments: Vec<RedisParameter>,
-        ) -> Result<Result<Vec<RedisResult>, redis_types::Error>> {
-            unreachable!()
-        }
-    }
+        build_python_app()?;
 
-    #[async_trait]
-    impl key_value::Host for Host {
-        async fn open(
-            &mut self,
-            _name: String,
-        ) -> Result<Result<key_value::Store, key_value::Error>> {
-            unreachable!()
-        }
+        App::build(&package_path("spin-app/spin.toml"))?;
 
-        async fn get(
-            &mut self,
-            _store: key_value::Store,
-            _key: String,
-        ) -> Result<Result<Vec<u8>, key_value::Error>> {
-            unreachable!()
-        }
+        Ok::<(), Error>(())
+    };
 
-        async fn set(
-            &mut self,
-            _store: key_value::Store,
-            _key: String,
-            _value: Vec<u8>,
-        ) -> Result<Result<(), key_value::Error>> {
-            unreachable!()
-        }
+    ONCE.call_once(|| once().unwrap())
+}
 
-        async fn delete(
-            &mut self,
-            _store: key_value::Store,
-            _key: String,
-        ) -> Result<Result<(), key_value::Error>> {
-            unreachable!()
-        }
+/// Whether each request is handled in the benchmark process itself, in a child forked for that request, in a
+/// worker pre-forked by a zygote, or in a child forked into its own namespaces and restricted by seccomp
+#[derive(Copy, Clone)]
+enum Mode {
+    Direct,
+    Fork,
+    Zygote,
+    Sandbox,
+}
 
-        async fn exists(
-            &mut self,
-            _store: key_value::Store,
-            _key: String,
-        ) -> Result<Result<bool, key_value::Error>> {
-            unreachable!()
-        }
+impl Mode {
+    /// Create a runtime for running guests in this mode
+    ///
+    /// Only the forking thread survives in a child process, so in all but direct mode we use a single-threaded
+    /// runtime which runs everything on that thread.
+    fn runtime(self) -> Result<Runtime> {
+        Ok(match self {
+            Self::Direct => Runtime::new()?,
+            Self::Fork | Self::Zygote | Self::Sandbox => runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+        })
+    }
+}
 
-        async fn get_keys(
-            &mut self,
-            _store: key_value::Store,
-        ) -> Result<Result<Vec<String>, key_value::Error>> {
-            unreachable!()
+fn bench(bencher: &mut Bencher, test: impl FnMut(), mode: Mode) -> Result<()> {
+    match mode {
+        Mode::Direct => bencher.iter(test),
+        Mode::Fork => bencher.iter(do_fork(test)),
+        Mode::Zygote => {
+            // Only the handoff to an idle worker and the request itself are timed; waiting for the zygote to
+            // replace the previous worker is not
+            let zygote = Zygote::start(zygote::POOL_SIZE, test)?;
+            bencher.iter_with_setup(
+                || zygote.wait_ready().unwrap(),
+                |()| zygote.request().unwrap(),
+            );
         }
-
-        async fn close(&mut self, _store: key_value::Store) -> Result<()> {
-            unreachable!()
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        Mode::Sandbox => {
+            let mut test = test;
+            let sandbox = sandbox::Sandbox::new()?;
+            bencher.iter(|| sandbox.run(&mut test).unwrap());
         }
-    }
-
-    #[async_trait]
-    impl http::Host for Host {
-        async fn send_request(
-            &mut self,
-            _req: RequestResult,
-        ) -> Result<Result<Response, HttpError>> {
-            unreachable!()
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Mode::Sandbox => {
+            return Err(anyhow!(
+                "sandbox mode is only supported on x86_64 and aarch64"
+            ))
         }
     }
 
-    fn add_to_linker(linker: &mut ComponentLinker<Host>) -> anyhow::Result<()> {
-        wasi_host::command::add_to_linker(linker, |host| &mut host.wasi)?;
-        config::add_to_linker(linker, |host| host)?;
-        redis::add_to_linker(linker, |host| host)?;
-        key_value::add_to_linker(linker, |host| host)?;
-        http::add_to_linker(linker, |host| host)?;
+    Ok(())
+}
 
-        Ok(())
-    }
+fn do_fork(mut fun: impl FnMut()) -> impl FnMut() {
+    move || {
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed; errno: {}", errno::errno()),
+            0 => {
+                // I'm the child.  A panic must not unwind past this point, or the child would carry on running the
+                // benchmark alongside its parent.
+                if panic::catch_unwind(AssertUnwindSafe(&mut fun)).is_err() {
+                    unsafe { libc::_exit(1) }
+                }
 
-    fn build_python_app() -> Result<()> {
-        let out_dir = Path::new(env!("OUT_DIR"));
-        let wasm_path = out_dir.join("python-spin-guest.wasm");
-        let py2wasm_path = out_dir.join("py2wasm");
-        if !py2wasm_path.exists() {
-            let tar = Runtime::new()?.block_on(async {
-                reqwest::get(&format!(
-                    "https://github.com/fermyon/spin-python-sdk/\
-                     releases/download/v0.1.1/py2wasm-v0.1.1-{}-{}.tar.gz",
-                    env::consts::OS,
-                    env::consts::ARCH
-                ))
-                .await?
-                .error_for_status()?
-                .bytes()
-                .await
-            })?;
-
-            let mut tar = Archive::new(GzDecoder::new(tar.deref()));
-
-            for file in tar.entries()? {
-                let mut file = file?;
-                if let Some("py2wasm") = file.path()?.to_str() {
-                    io::copy(
-                        &mut file,
-                        &mut OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .mode(0o744)
-                            .open(&py2wasm_path)?,
-                    )?;
-                    break;
+                // Exit without running any destructors for maximum performance
+                unsafe { libc::_exit(0) }
+            }
+            child => {
+                // I'm the parent
+                let mut status = 0;
+                if -1 == unsafe { libc::waitpid(child, &mut status, 0) } {
+                    panic!("waitpid failed; errno: {}", errno::errno());
+                }
+
+                if !(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0) {
+                    panic!(
+                        "child exited{}",
+                        if libc::WIFEXITED(status) {
+                            format!(" (exit status {})", libc::WEXITSTATUS(status))
+                        } else if libc::WIFSIGNALED(status) {
+                            format!(" (killed by signal {})", libc::WTERMSIG(status))
+                        } else {
+                            String::new()
+                        }
+                    )
                 }
             }
         }
+    }
+}
 
-        assert!(Command::new(py2wasm_path)
-            .current_dir("python-spin-guest")
-            .arg("app")
-            .arg("-o")
-            .arg(wasm_path)
-            .status()?
-            .success());
+fn spin_native_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    // not actually needed here, but it makes the output more readable to get the compilation step done before
+    // any benchmarks run:
+    compile_guests();
+
+    let handle_request =
+        <spin_guest::InboundHttp as spin_guest::inbound_http::InboundHttp>::handle_request;
+
+    bench(
+        bencher,
+        || {
+            let response = hint::black_box(handle_request)(spin_guest::RequestResult {
+                method: spin_guest::Method::Post,
+                uri: "/foo?a=b".to_owned(),
+                headers: vec![("what".to_owned(), "up".to_owned())],
+                params: Vec::new(),
+                body: Some(b"hello, world!".to_vec()),
+            });
+
+            let headers = response.headers.unwrap();
+            assert_eq!(1, headers.len());
+            assert_eq!("content-type", headers[0].0);
+            assert_eq!("text/plain", headers[0].1);
+            assert_eq!(Some(b"hola, mundo!" as &[_]), response.body.as_deref());
+        },
+        mode,
+    )
+}
 
-        Ok(())
+/// The request every guest expects, i.e. the one `spin_test_func` sends
+fn test_request() -> IncomingRequest {
+    IncomingRequest {
+        method: Method::Post,
+        uri: "/foo?a=b".to_owned(),
+        headers: vec![("what".to_owned(), "up".to_owned())],
+        body: Some(b"hello, world!".to_vec()),
     }
+}
 
-    fn compile_guests() {
-        static ONCE: Once = Once::new();
+/// Assert that `response` is what every guest returns for `test_request`
+fn check_test_response(response: Response) {
+    assert_eq!(200, response.status);
+    let headers = response.headers.unwrap();
+    assert_eq!(1, headers.len());
+    assert_eq!("content-type", headers[0].0);
+    assert_eq!("text/plain", headers[0].1);
+    assert_eq!(Some(b"hola, mundo!" as &[_]), response.body.as_deref());
+}
 
-        let once = || {
-            for guest in ["wagi-guest", "spin-guest", "spin-sdk-guest"] {
-                let mut cmd = Command::new("cargo");
-                cmd.arg("build")
-                    .current_dir(guest)
-                    .arg("--release")
-                    .arg("--target=wasm32-wasi")
-                    .env("CARGO_TARGET_DIR", env!("OUT_DIR"));
+async fn spin_test_func(
+    store: &mut Store<Host>,
+    func: ComponentTypedFunc<(RequestParam<'_>,), (Response,)>,
+) -> Result<()> {
+    let (response,) = func
+        .call_async(
+            &mut *store,
+            (RequestParam {
+                method: Method::Post,
+                uri: "/foo?a=b",
+                headers: &[("what", "up")],
+                params: &[],
+                body: Some(b"hello, world!"),
+            },),
+        )
+        .await?;
 
-                let status = cmd.status()?;
-                assert!(status.success());
-            }
+    let headers = response.headers.unwrap();
+    assert_eq!(1, headers.len());
+    assert_eq!("content-type", headers[0].0);
+    assert_eq!("text/plain", headers[0].1);
+    assert_eq!(Some(b"hola, mundo!" as &[_]), response.body.as_deref());
 
-            build_python_app()?;
+    func.post_return_async(store).await
+}
 
-            Ok::<(), Error>(())
-        };
+async fn spin_test_instance(store: &mut Store<Host>, instance: &ComponentInstance) -> Result<()> {
+    let func = instance
+        .exports(&mut *store)
+        .instance("inbound-http")
+        .ok_or_else(|| anyhow!("no inbound-http instance found"))?
+        .typed_func::<(RequestParam,), (Response,)>("handle-request")?;
+    spin_test_func(store, func).await
+}
 
-        ONCE.call_once(|| once().unwrap())
-    }
+/// Create an engine from `config` with async support and the component model enabled, plus a linker with every
+/// host interface added
+fn spin_engine(mut config: Config) -> Result<(Engine, ComponentLinker<Host>)> {
+    config.async_support(true);
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let mut linker = ComponentLinker::new(&engine);
+    add_to_linker(&mut linker)?;
+    Ok((engine, linker))
+}
 
-    enum Mode {
-        Direct,
-        Fork,
-    }
+/// Read the guest module at `wasm_path` (relative to the build output directory) and componentize it
+fn read_component(wasm_path: &str) -> Result<Vec<u8>> {
+    spin_componentize::componentize(&fs::read(format!("{}{wasm_path}", env!("OUT_DIR")))?)
+}
 
-    fn bench(bencher: &mut Bencher, test: impl Fn(), mode: Mode) {
-        match mode {
-            Mode::Direct => bencher.iter(test),
-            Mode::Fork => bencher.iter(do_fork(test)),
-        }
-    }
+fn spin_instance_pre(
+    wasm_path: &str,
+    config: Config,
+) -> Result<(ComponentInstancePre<Host>, Engine)> {
+    let (engine, linker) = spin_engine(config)?;
+    Ok((
+        linker.instantiate_pre(&Component::new(&engine, read_component(wasm_path)?)?)?,
+        engine,
+    ))
+}
 
-    fn do_fork(fun: impl Fn()) -> impl Fn() {
-        move || {
-            match unsafe { libc::fork() } {
-                -1 => panic!("fork failed; errno: {}", errno::errno()),
-                0 => {
-                    // I'm the child
-                    fun();
+fn stdio_host() -> Host {
+    Host::new(
+        wasmtime_wasi_preview2::WasiCtxBuilder::new()
+            .inherit_stdout()
+            .inherit_stderr()
+            .build(),
+    )
+}
 
-                    // Exit without running any destructors for maximum performance
-                    unsafe { libc::_exit(0) }
-                }
-                child => {
-                    // I'm the parent
-                    let mut status = 0;
-                    if -1 == unsafe { libc::waitpid(child, &mut status, 0) } {
-                        panic!("waitpid failed; errno: {}", errno::errno());
-                    }
-
-                    if !(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0) {
-                        panic!(
-                            "child exited{}",
-                            if libc::WIFEXITED(status) {
-                                format!(" (exit status {})", libc::WEXITSTATUS(status))
-                            } else if libc::WIFSIGNALED(status) {
-                                format!(" (killed by signal {})", libc::WTERMSIG(status))
-                            } else {
-                                String::new()
-                            }
-                        )
-                    }
-                }
-            }
-        }
-    }
+fn spin_response(bencher: &mut Bencher, wasm_path: &str, config: Config, mode: Mode) -> Result<()> {
+    spin_response_with_host(bencher, wasm_path, config, stdio_host, mode)
+}
 
-    fn spin_native_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
-        // not actually needed here, but it makes the output more readable to get the compilation step done before
-        // any benchmarks run:
-        compile_guests();
-
-        let handle_request =
-            <spin_guest::InboundHttp as spin_guest::inbound_http::InboundHttp>::handle_request;
-
-        bench(
-            bencher,
-            || {
-                let response = hint::black_box(handle_request)(spin_guest::RequestResult {
-                    method: spin_guest::Method::Post,
-                    uri: "/foo?a=b".to_owned(),
-                    headers: vec![("what".to_owned(), "up".to_owned())],
-                    params: Vec::new(),
-                    body: Some(b"hello, world!".to_vec()),
-                });
-
-                let headers = response.headers.unwrap();
-                assert_eq!(1, headers.len());
-                assert_eq!("content-type", headers[0].0);
-                assert_eq!("text/plain", headers[0].1);
-                assert_eq!(Some(b"hola, mundo!" as &[_]), response.body.as_deref());
-            },
-            mode,
-        );
+/// Benchmark a guest using an `InstancePre`; in fork mode, it's created before forking, and each child
+/// instantiates it
+fn spin_response_with_host(
+    bencher: &mut Bencher,
+    wasm_path: &str,
+    config: Config,
+    make_host: impl Fn() -> Host,
+    mode: Mode,
+) -> Result<()> {
+    compile_guests();
 
-        Ok(())
-    }
+    let (pre, engine) = spin_instance_pre(wasm_path, config)?;
+
+    let run = || async {
+        let mut store = Store::new(&engine, make_host());
+        let instance = pre.instantiate_async(&mut store).await?;
+
+        spin_test_instance(&mut store, &instance).await
+    };
+
+    let runtime = mode.runtime()?;
+
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
 
-    async fn spin_test_func(
-        store: &mut Store<Host>,
-        func: ComponentTypedFunc<(RequestParam<'_>,), (Response,)>,
-    ) -> Result<()> {
-        let (response,) = func
-            .call_async(
-                &mut *store,
-                (RequestParam {
-                    method: Method::Post,
-                    uri: "/foo?a=b",
-                    headers: &[("what", "up")],
-                    params: &[],
-                    body: Some(b"hello, world!"),
-                },),
+/// Benchmark a guest which sends an outbound request to a local backend; in fork mode, the backend keeps serving
+/// from the parent
+fn spin_outbound_http_response(
+    bencher: &mut Bencher,
+    limits: RateLimits,
+    mode: Mode,
+) -> Result<()> {
+    // The backend gets its own runtime so that it keeps serving between `block_on` calls made by the benchmark
+    let backend_runtime = Runtime::new()?;
+    let backend = backend_runtime.block_on(async { Backend::start() })?;
+
+    let config = Arc::new(ConfigResolver::new(&format!(
+        "config = {{ backend_url = {:?} }}",
+        backend.url("/hola")
+    ))?);
+    let http =
+        Arc::new(OutboundHttp::new(AllowedHosts::parse(&[&backend.url("")])?).limits(limits));
+
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_outbound_http_guest.wasm",
+        Config::new(),
+        || Host {
+            config: config.clone(),
+            http: http.clone(),
+            ..stdio_host()
+        },
+        mode,
+    )
+}
+
+/// Benchmark requests sent over loopback to a front-end using `strategy`; in fork mode, each child sends a request
+/// to the front-end, which keeps serving from the parent
+fn spin_loopback_response(bencher: &mut Bencher, strategy: Strategy, mode: Mode) -> Result<()> {
+    compile_guests();
+
+    // The front-end gets its own runtime so that it keeps serving between `block_on` calls made by the benchmark
+    let server_runtime = Runtime::new()?;
+    let front_end = server_runtime.block_on(async {
+        FrontEnd::start(
+            Dispatcher::new("/wasm32-wasi/release/spin_guest.wasm", strategy, stdio_host).await?,
+        )
+    })?;
+
+    let url = front_end.url("/foo?a=b");
+    let client = hyper::Client::new();
+
+    let run = || async {
+        let response = client
+            .request(
+                hyper::Request::post(&url)
+                    .header("what", "up")
+                    .body(hyper::Body::from("hello, world!"))?,
             )
             .await?;
 
-        let headers = response.headers.unwrap();
-        assert_eq!(1, headers.len());
-        assert_eq!("content-type", headers[0].0);
-        assert_eq!("text/plain", headers[0].1);
-        assert_eq!(Some(b"hola, mundo!" as &[_]), response.body.as_deref());
+        assert_eq!(200, response.status());
+        assert_eq!("text/plain", response.headers()["content-type"]);
+        assert_eq!(
+            b"hola, mundo!" as &[_],
+            &hyper::body::to_bytes(response.into_body()).await?
+        );
 
-        func.post_return_async(store).await
-    }
+        Ok::<(), Error>(())
+    };
 
-    async fn spin_test_instance(
-        store: &mut Store<Host>,
-        instance: &ComponentInstance,
-    ) -> Result<()> {
-        let func = instance
-            .exports(&mut *store)
-            .instance("inbound-http")
-            .ok_or_else(|| anyhow!("no inbound-http instance found"))?
-            .typed_func::<(RequestParam,), (Response,)>("handle-request")?;
-        spin_test_func(store, func).await
-    }
+    let runtime = mode.runtime()?;
 
-    fn spin_instance_pre(
-        wasm_path: &str,
-        mut config: Config,
-    ) -> Result<(ComponentInstancePre<Host>, Engine)> {
-        config.async_support(true);
-        config.wasm_component_model(true);
-        let engine = Engine::new(&config)?;
-        let mut linker = ComponentLinker::new(&engine);
-        add_to_linker(&mut linker)?;
-        Ok((
-            linker.instantiate_pre(&Component::new(
-                &engine,
-                spin_componentize::componentize(&fs::read(format!(
-                    "{}{wasm_path}",
-                    env!("OUT_DIR")
-                ))?)?,
-            )?)?,
-            engine,
-        ))
-    }
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
 
-    fn spin_response(bencher: &mut Bencher, wasm_path: &str, config: Config) -> Result<()> {
-        compile_guests();
+fn spin_native_direct_response(bencher: &mut Bencher) -> Result<()> {
+    spin_native_response(bencher, Mode::Direct)
+}
 
-        let (pre, engine) = spin_instance_pre(wasm_path, config)?;
+fn spin_native_fork_response(bencher: &mut Bencher) -> Result<()> {
+    spin_native_response(bencher, Mode::Fork)
+}
 
-        let run = || async {
-            let mut store = Store::new(
-                &engine,
-                Host {
-                    wasi: wasmtime_wasi_preview2::WasiCtxBuilder::new()
-                        .inherit_stdout()
-                        .inherit_stderr()
-                        .build(),
-                },
-            );
-            let instance = pre.instantiate_async(&mut store).await?;
+fn spin_native_zygote_response(bencher: &mut Bencher) -> Result<()> {
+    spin_native_response(bencher, Mode::Zygote)
+}
 
-            spin_test_instance(&mut store, &instance).await
-        };
+fn spin_native_sandbox_response(bencher: &mut Bencher) -> Result<()> {
+    spin_native_response(bencher, Mode::Sandbox)
+}
 
-        let runtime = Runtime::new()?;
+fn wagi_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    compile_guests();
 
-        bencher.iter(|| runtime.block_on(run()).unwrap());
+    let executor = WagiExecutor::new(
+        "/wasm32-wasi/release/wagi-guest.wasm",
+        Config::new(),
+        Mount::default(),
+    )?;
 
-        Ok(())
-    }
+    let run = || async {
+        let response = executor.execute(&test_request()).await?;
 
-    #[bench]
-    fn spin_native_direct_response(bencher: &mut Bencher) -> Result<()> {
-        spin_native_response(bencher, Mode::Direct)
-    }
+        check_test_response(response);
 
-    #[bench]
-    fn spin_native_fork_response(bencher: &mut Bencher) -> Result<()> {
-        spin_native_response(bencher, Mode::Fork)
-    }
+        Ok::<(), Error>(())
+    };
 
-    #[bench]
-    fn wagi_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
-        compile_guests();
+    let runtime = mode.runtime()?;
 
-        let mut config = Config::new();
-        config.async_support(true);
-        let engine = &Engine::new(&config)?;
-        let mut linker = ModuleLinker::new(engine);
-        wasmtime_wasi_preview1::add_to_linker(&mut linker, |ctx| ctx)?;
-        let pre = linker.instantiate_pre(&Module::new(
-            engine,
-            fs::read(concat!(
-                env!("OUT_DIR"),
-                "/wasm32-wasi/release/wagi-guest.wasm"
-            ))?,
-        )?)?;
-
-        let run = || async {
-            let mut ctx = wasmtime_wasi_preview1::WasiCtxBuilder::new().build();
-            ctx.push_arg("/foo")?;
-            ctx.push_arg("a=b")?;
-            ctx.push_env("what", "up")?;
-            ctx.set_stdin(Box::new(wasi_preview1::pipe::ReadPipe::new(
-                b"hello, world!" as &[_],
-            )));
-            let stdout = wasi_preview1::pipe::WritePipe::new_in_memory();
-            ctx.set_stdout(Box::new(stdout.clone()));
-            let stderr = wasi_preview1::pipe::WritePipe::new_in_memory();
-            ctx.set_stderr(Box::new(stderr.clone()));
-
-            let mut store = Store::new(engine, ctx);
-            let instance = pre.instantiate_async(&mut store).await?;
-
-            let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
-
-            let result = start.call_async(&mut store, ()).await;
-
-            drop(store);
-
-            result.with_context(|| {
-                String::from_utf8_lossy(&stderr.try_into_inner().unwrap().into_inner()).to_string()
-            })?;
-
-            assert_eq!(
-                b"content-type: text/plain\n\nhola, mundo!\n" as &[_],
-                &stdout.try_into_inner().unwrap().into_inner()
-            );
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
 
-            Ok::<(), Error>(())
-        };
+fn wagi_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    wagi_response(bencher, Mode::Direct)
+}
 
-        let runtime = Runtime::new()?;
+fn wagi_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    wagi_response(bencher, Mode::Fork)
+}
 
-        bencher.iter(|| runtime.block_on(run()).unwrap());
+fn wagi_native_cgi_response(bencher: &mut Bencher) -> Result<()> {
+    compile_guests();
 
-        Ok(())
-    }
+    let executor = CgiExecutor::new(
+        concat!(env!("OUT_DIR"), "/release/wagi-guest"),
+        Mount::default(),
+    );
 
-    #[bench]
-    fn spin_rust_sdk_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
-        spin_response(
-            bencher,
-            "/wasm32-wasi/release/spin_sdk_guest.wasm",
-            Config::new(),
-        )
-    }
+    bencher.iter(|| check_test_response(executor.execute(&test_request()).unwrap()));
 
-    #[bench]
-    fn spin_python_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
-        spin_response(bencher, "/python-spin-guest.wasm", Config::new())
-    }
+    Ok(())
+}
 
-    #[bench]
-    fn spin_rust_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
-        spin_response(
-            bencher,
-            "/wasm32-wasi/release/spin_guest.wasm",
-            Config::new(),
-        )
-    }
+fn spin_rust_sdk_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_sdk_guest.wasm",
+        Config::new(),
+        Mode::Direct,
+    )
+}
+
+fn spin_rust_sdk_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_sdk_guest.wasm",
+        Config::new(),
+        Mode::Fork,
+    )
+}
+
+fn spin_python_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/python-spin-guest.wasm",
+        Config::new(),
+        Mode::Direct,
+    )
+}
+
+fn spin_python_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/python-spin-guest.wasm",
+        Config::new(),
+        Mode::Fork,
+    )
+}
+
+fn spin_rust_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_guest.wasm",
+        Config::new(),
+        Mode::Direct,
+    )
+}
+
+fn spin_rust_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_guest.wasm",
+        Config::new(),
+        Mode::Fork,
+    )
+}
+
+fn spin_rust_response_pre_instance_zygote(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_guest.wasm",
+        Config::new(),
+        Mode::Zygote,
+    )
+}
+
+/// Benchmark the key-value guest against an in-memory store; in fork mode, whatever a child writes is discarded
+/// when it exits
+fn spin_kv_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    let config = Arc::new(KeyValueConfig::with_default_store());
+
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_kv_guest.wasm",
+        Config::new(),
+        || Host {
+            key_value: KeyValue::new(config.clone()),
+            ..stdio_host()
+        },
+        mode,
+    )
+}
+
+fn spin_rust_kv_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_kv_response(bencher, Mode::Direct)
+}
+
+fn spin_rust_kv_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_kv_response(bencher, Mode::Fork)
+}
+
+// There's no fork variant of this one, since SQLite doesn't support using a connection opened before `fork` in
+// the child, and opening one per request would mostly measure that instead
+fn spin_rust_kv_sqlite_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let config =
+        Arc::new(KeyValueConfig::default().sqlite_store("default", &tempdir.path().join("kv.db"))?);
+
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_kv_guest.wasm",
+        Config::new(),
+        || Host {
+            key_value: KeyValue::new(config.clone()),
+            ..stdio_host()
+        },
+        Mode::Direct,
+    )
+}
 
-    #[bench]
-    fn spin_rust_response_reuse_instance(bencher: &mut Bencher) -> Result<()> {
-        compile_guests();
+fn spin_redis_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    let redis = Arc::new(RedisStore::default());
 
-        let (pre, engine) =
-            spin_instance_pre("/wasm32-wasi/release/spin_guest.wasm", Config::new())?;
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_redis_guest.wasm",
+        Config::new(),
+        || Host {
+            redis: RedisBackend::Embedded(redis.clone()),
+            ..stdio_host()
+        },
+        mode,
+    )
+}
 
+fn spin_rust_redis_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_redis_response(bencher, Mode::Direct)
+}
+
+fn spin_rust_redis_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_redis_response(bencher, Mode::Fork)
+}
+
+/// Benchmark the Redis guest against a local RESP server; in fork mode, the server keeps serving from the parent
+fn spin_redis_resp_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    // The server gets its own runtime so that it keeps serving between `block_on` calls made by the benchmark
+    let server_runtime = Runtime::new()?;
+    let server = server_runtime.block_on(RespServer::start(Arc::default()))?;
+    let client = Arc::new(RespClient::default().alias("redis://127.0.0.1:6379", server.address()));
+
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_redis_guest.wasm",
+        Config::new(),
+        || Host {
+            redis: RedisBackend::Resp(client.clone()),
+            ..stdio_host()
+        },
+        mode,
+    )
+}
+
+fn spin_rust_redis_resp_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_redis_resp_response(bencher, Mode::Direct)
+}
+
+fn spin_rust_redis_resp_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_redis_resp_response(bencher, Mode::Fork)
+}
+
+fn spin_config_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path().join("variables.toml");
+    fs::write(&path, r#"place = "mundo""#)?;
+
+    let config = Arc::new(
+        ConfigResolver::new(
+            r#"
+            [variables]
+            greeting = { default = "hola" }
+            place = { required = true }
+            punctuation = { default = "!" }
+
+            [config]
+            message = "{{ greeting }}, {{ place }}{{ punctuation }}"
+            content_type = "text/plain"
+            "#,
+        )?
+        .provider(TomlProvider::read(&path)?)
+        .provider(EnvProvider::default()),
+    );
+
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_config_guest.wasm",
+        Config::new(),
+        || Host {
+            config: config.clone(),
+            ..stdio_host()
+        },
+        mode,
+    )
+}
+
+fn spin_rust_config_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_config_response(bencher, Mode::Direct)
+}
+
+fn spin_rust_config_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_config_response(bencher, Mode::Fork)
+}
+
+fn spin_sql_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    let sql = Arc::new(SqlEngine::default());
+
+    spin_response_with_host(
+        bencher,
+        "/wasm32-wasi/release/spin_sql_guest.wasm",
+        Config::new(),
+        || Host {
+            sql: sql.clone(),
+            ..stdio_host()
+        },
+        mode,
+    )
+}
+
+fn spin_rust_sql_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_sql_response(bencher, Mode::Direct)
+}
+
+fn spin_rust_sql_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_sql_response(bencher, Mode::Fork)
+}
+
+fn spin_rust_outbound_http_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_outbound_http_response(bencher, RateLimits::default(), Mode::Direct)
+}
+
+fn spin_rust_outbound_http_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_outbound_http_response(bencher, RateLimits::default(), Mode::Fork)
+}
+
+/// Limits which are never reached, so we measure the cost of enforcing them
+fn unreached_limits() -> RateLimits {
+    let limit = Limit {
+        rate: Some((u32::MAX, u32::MAX.into())),
+        max_in_flight: Some(usize::MAX),
+    };
+
+    RateLimits::default()
+        .component(limit)
+        .per_destination(limit)
+}
+
+fn spin_rust_outbound_http_rate_limited_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_outbound_http_response(bencher, unreached_limits(), Mode::Direct)
+}
+
+fn spin_rust_outbound_http_rate_limited_response_pre_instance_fork(
+    bencher: &mut Bencher,
+) -> Result<()> {
+    spin_outbound_http_response(bencher, unreached_limits(), Mode::Fork)
+}
+
+fn spin_rust_loopback_response(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::Fresh, Mode::Direct)
+}
+
+fn spin_rust_loopback_response_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::Fresh, Mode::Fork)
+}
+
+fn spin_rust_loopback_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::PreInstance, Mode::Direct)
+}
+
+fn spin_rust_loopback_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::PreInstance, Mode::Fork)
+}
+
+fn spin_rust_loopback_response_pre_instance_with_pooling(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::Pooling, Mode::Direct)
+}
+
+fn spin_rust_loopback_response_pre_instance_with_pooling_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::Pooling, Mode::Fork)
+}
+
+fn spin_rust_loopback_response_reuse_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::Reuse, Mode::Direct)
+}
+
+fn spin_rust_loopback_response_reuse_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_loopback_response(bencher, Strategy::Reuse, Mode::Fork)
+}
+
+fn spin_app_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    compile_guests();
+
+    let app = App::load(&package_path("spin-app/spin.toml"), Config::new())?;
+
+    // Each iteration sends one request to every component in the app
+    let run = || async {
+        for component in app.components() {
+            let mut store = Store::new(app.engine(), component.host());
+            let instance = component.instantiate(&mut store).await?;
+
+            spin_test_instance(&mut store, &instance)
+                .await
+                .with_context(|| format!("component {:?} failed", component.id))?;
+        }
+
+        Ok::<(), Error>(())
+    };
+
+    let runtime = mode.runtime()?;
+
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
+
+fn spin_app_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_app_response(bencher, Mode::Direct)
+}
+
+fn spin_app_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_app_response(bencher, Mode::Fork)
+}
+
+fn spin_app_routed_response(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    compile_guests();
+
+    let app = App::load(&package_path("spin-app/spin.toml"), Config::new())?;
+
+    // Routing is included in the measurement, since it's part of dispatching each request
+    let run = || async {
+        let response = app.dispatch(&test_request()).await?;
+
+        check_test_response(response);
+
+        Ok::<(), Error>(())
+    };
+
+    let runtime = mode.runtime()?;
+
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
+
+fn spin_app_routed_response_pre_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_app_routed_response(bencher, Mode::Direct)
+}
+
+fn spin_app_routed_response_pre_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_app_routed_response(bencher, Mode::Fork)
+}
+
+/// Benchmark a single, long-lived instance; in fork mode, it's created before forking, and each child calls it
+fn spin_response_reuse(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    compile_guests();
+
+    let (pre, engine) = spin_instance_pre("/wasm32-wasi/release/spin_guest.wasm", Config::new())?;
+
+    let mut store = Store::new(&engine, stdio_host());
+
+    let runtime = mode.runtime()?;
+
+    let instance = runtime.block_on(pre.instantiate_async(&mut store))?;
+    let func = instance
+        .exports(&mut store)
+        .instance("inbound-http")
+        .ok_or_else(|| anyhow!("no inbound-http instance found"))?
+        .typed_func::<(RequestParam,), (Response,)>("handle-request")?;
+
+    bench(
+        bencher,
+        || runtime.block_on(spin_test_func(&mut store, func)).unwrap(),
+        mode,
+    )
+}
+
+fn spin_rust_response_reuse_instance(bencher: &mut Bencher) -> Result<()> {
+    spin_response_reuse(bencher, Mode::Direct)
+}
+
+fn spin_rust_response_reuse_instance_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response_reuse(bencher, Mode::Fork)
+}
+
+fn spin_rust_response_pre_instance_with_pooling(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_guest.wasm",
+        Strategy::Pooling.config(),
+        Mode::Direct,
+    )
+}
+
+fn spin_rust_response_pre_instance_with_pooling_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_guest.wasm",
+        Strategy::Pooling.config(),
+        Mode::Fork,
+    )
+}
+
+fn spin_rust_response_pre_instance_with_pooling_zygote(bencher: &mut Bencher) -> Result<()> {
+    spin_response(
+        bencher,
+        "/wasm32-wasi/release/spin_guest.wasm",
+        Strategy::Pooling.config(),
+        Mode::Zygote,
+    )
+}
+
+/// Benchmark compiling and instantiating the component for each request; in fork mode, each child does both
+fn spin_response_fresh(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    compile_guests();
+
+    let mut config = Strategy::Fresh.config();
+    if !matches!(mode, Mode::Direct) {
+        // Compilation would otherwise use a thread pool which, if the parent already started it, doesn't exist in
+        // the child, causing it to hang
+        config.parallel_compilation(false);
+    }
+    let (engine, linker) = spin_engine(config)?;
+    let engine = &engine;
+    let component = read_component("/wasm32-wasi/release/spin_guest.wasm")?;
+
+    let run = || async {
         let mut store = Store::new(
-            &engine,
-            Host {
-                wasi: wasmtime_wasi_preview2::WasiCtxBuilder::new()
-                    .inherit_stdout()
-                    .inherit_stderr()
-                    .build(),
-            },
+            engine,
+            Host::new(wasmtime_wasi_preview2::WasiCtxBuilder::new().build()),
         );
+        let instance = linker
+            .instantiate_async(&mut store, &Component::new(engine, &component)?)
+            .await?;
 
-        let runtime = Runtime::new()?;
+        spin_test_instance(&mut store, &instance).await
+    };
 
-        let instance = runtime.block_on(pre.instantiate_async(&mut store))?;
-        let func = instance
-            .exports(&mut store)
-            .instance("inbound-http")
-            .ok_or_else(|| anyhow!("no inbound-http instance found"))?
-            .typed_func::<(RequestParam,), (Response,)>("handle-request")?;
+    let runtime = mode.runtime()?;
 
-        bencher.iter(|| runtime.block_on(spin_test_func(&mut store, func)).unwrap());
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
 
-        Ok(())
-    }
+fn spin_rust_response(bencher: &mut Bencher) -> Result<()> {
+    spin_response_fresh(bencher, Mode::Direct)
+}
 
-    #[bench]
-    fn spin_rust_response_pre_instance_with_pooling(bencher: &mut Bencher) -> Result<()> {
-        let mut config = Config::new();
-        config.allocation_strategy(InstanceAllocationStrategy::Pooling(
-            PoolingAllocationConfig::default(),
-        ));
-        spin_response(bencher, "/wasm32-wasi/release/spin_guest.wasm", config)
-    }
+fn spin_rust_response_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response_fresh(bencher, Mode::Fork)
+}
 
-    #[bench]
-    fn spin_rust_response(bencher: &mut Bencher) -> Result<()> {
-        compile_guests();
-
-        let mut config = Config::new();
-        config.async_support(true);
-        config.wasm_component_model(true);
-        let engine = &Engine::new(&config)?;
-        let mut linker = ComponentLinker::new(engine);
-        add_to_linker(&mut linker)?;
-        let component = spin_componentize::componentize(&fs::read(format!(
-            "{}/wasm32-wasi/release/spin_guest.wasm",
-            env!("OUT_DIR")
-        ))?)?;
-
-        let run = || async {
-            let mut store = Store::new(
-                engine,
-                Host {
-                    wasi: wasmtime_wasi_preview2::WasiCtxBuilder::new().build(),
-                },
-            );
-            let instance = linker
-                .instantiate_async(&mut store, &Component::new(engine, &component)?)
-                .await?;
+/// Benchmark deserializing a precompiled component and instantiating it for each request; in fork mode, each child
+/// does both
+fn spin_response_pre_compile(bencher: &mut Bencher, mode: Mode) -> Result<()> {
+    compile_guests();
 
-            spin_test_instance(&mut store, &instance).await
-        };
+    let (engine, linker) = spin_engine(Strategy::Fresh.config())?;
+    let engine = &engine;
+    let component = read_component("/wasm32-wasi/release/spin_guest.wasm")?;
+    let cwasm = engine.precompile_component(&component)?;
+    let tempdir = tempfile::tempdir()?;
+    let file = tempdir.path().join("foo.cwasm");
+    fs::write(&file, &cwasm)?;
 
-        let runtime = Runtime::new()?;
+    let run = || async {
+        let mut store = Store::new(
+            engine,
+            Host::new(wasmtime_wasi_preview2::WasiCtxBuilder::new().build()),
+        );
+        let instance = linker
+            .instantiate_async(&mut store, &unsafe {
+                Component::deserialize_file(engine, &file)
+            }?)
+            .await?;
 
-        bencher.iter(|| runtime.block_on(run()).unwrap());
+        spin_test_instance(&mut store, &instance).await
+    };
 
-        Ok(())
-    }
+    let runtime = mode.runtime()?;
 
-    #[bench]
-    fn spin_rust_response_pre_compile(bencher: &mut Bencher) -> Result<()> {
-        compile_guests();
-
-        let mut config = Config::new();
-        config.async_support(true);
-        config.wasm_component_model(true);
-        let engine = &Engine::new(&config)?;
-        let mut linker = ComponentLinker::new(engine);
-        add_to_linker(&mut linker)?;
-        let component = spin_componentize::componentize(&fs::read(format!(
-            "{}/wasm32-wasi/release/spin_guest.wasm",
-            env!("OUT_DIR")
-        ))?)?;
-        let cwasm = engine.precompile_component(&component)?;
-        let tempdir = tempfile::tempdir()?;
-        let file = tempdir.path().join("foo.cwasm");
-        fs::write(&file, &cwasm)?;
-
-        let run = || async {
-            let mut store = Store::new(
-                engine,
-                Host {
-                    wasi: wasmtime_wasi_preview2::WasiCtxBuilder::new().build(),
-                },
-            );
-            let instance = linker
-                .instantiate_async(&mut store, &unsafe {
-                    Component::deserialize_file(engine, &file)
-                }?)
-                .await?;
+    bench(bencher, || runtime.block_on(run()).unwrap(), mode)
+}
 
-            spin_test_instance(&mut store, &instance).await
-        };
+fn spin_rust_response_pre_compile(bencher: &mut Bencher) -> Result<()> {
+    spin_response_pre_compile(bencher, Mode::Direct)
+}
 
-        let runtime = Runtime::new()?;
+fn spin_rust_response_pre_compile_fork(bencher: &mut Bencher) -> Result<()> {
+    spin_response_pre_compile(bencher, Mode::Fork)
+}
 
-        bencher.iter(|| runtime.block_on(run()).unwrap());
+/// A named benchmark scenario
+pub struct Scenario {
+    pub name: &'static str,
+    pub run: fn(&mut Bencher) -> Result<()>,
+}
 
-        Ok(())
-    }
+macro_rules! scenarios {
+    ($($name:ident),* $(,)?) => {
+        /// All benchmark scenarios, in the order the runner executes them
+        pub const SCENARIOS: &[Scenario] = &[$(Scenario {
+            name: stringify!($name),
+            run: $name,
+        }),*];
+    };
 }
+
+scenarios![
+    spin_native_direct_response,
+    spin_native_fork_response,
+    spin_native_zygote_response,
+    spin_native_sandbox_response,
+    wagi_response_pre_instance,
+    wagi_native_cgi_response,
+    spin_rust_sdk_response_pre_instance,
+    spin_python_response_pre_instance,
+    spin_rust_response_pre_instance,
+    spin_rust_kv_response_pre_instance,
+    spin_rust_kv_sqlite_response_pre_instance,
+    spin_rust_redis_response_pre_instance,
+    spin_rust_redis_resp_response_pre_instance,
+    spin_rust_config_response_pre_instance,
+    spin_rust_sql_response_pre_instance,
+    spin_rust_outbound_http_response_pre_instance,
+    spin_rust_outbound_http_rate_limited_response_pre_instance,
+    spin_rust_loopback_response,
+    spin_rust_loopback_response_pre_instance,
+    spin_rust_loopback_response_pre_instance_with_pooling,
+    spin_rust_loopback_response_reuse_instance,
+    spin_app_response_pre_instance,
+    spin_app_routed_response_pre_instance,
+    spin_rust_response_reuse_instance,
+    spin_rust_response_pre_instance_with_pooling,
+    spin_rust_response,
+    spin_rust_response_pre_compile,
+    wagi_response_pre_instance_fork,
+    spin_rust_sdk_response_pre_instance_fork,
+    spin_python_response_pre_instance_fork,
+    spin_rust_response_pre_instance_fork,
+    spin_rust_kv_response_pre_instance_fork,
+    spin_rust_redis_response_pre_instance_fork,
+    spin_rust_redis_resp_response_pre_instance_fork,
+    spin_rust_config_response_pre_instance_fork,
+    spin_rust_sql_response_pre_instance_fork,
+    spin_rust_outbound_http_response_pre_instance_fork,
+    spin_rust_outbound_http_rate_limited_response_pre_instance_fork,
+    spin_rust_loopback_response_fork,
+    spin_rust_loopback_response_pre_instance_fork,
+    spin_rust_loopback_response_pre_instance_with_pooling_fork,
+    spin_rust_loopback_response_reuse_instance_fork,
+    spin_app_response_pre_instance_fork,
+    spin_app_routed_response_pre_instance_fork,
+    spin_rust_response_reuse_instance_fork,
+    spin_rust_response_pre_instance_with_pooling_fork,
+    spin_rust_response_fork,
+    spin_rust_response_pre_compile_fork,
+    spin_rust_response_pre_instance_zygote,
+    spin_rust_response_pre_instance_with_pooling_zygote,
+];
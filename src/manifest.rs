@@ -0,0 +1,360 @@
+//! Loader for Spin v1 application manifests (i.e. `spin.toml`), which builds, componentizes, and pre-instantiates
+//! every component in an application so the whole app can be benchmarked rather than a single handler
+
+use {
+    super::{
+        add_to_linker,
+        front_end::{call, handle_request_func, IncomingRequest},
+        outbound_http::{AllowedHosts, OutboundHttp},
+        router::Router,
+        stdio_host,
+        variables::{ConfigResolver, EnvProvider},
+        Host, Response,
+    },
+    anyhow::{anyhow, bail, ensure, Context, Result},
+    serde::Deserialize,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        process::Command,
+        sync::Arc,
+    },
+    wasmtime::{
+        component::{
+            Component, Instance as ComponentInstance, InstancePre as ComponentInstancePre,
+            Linker as ComponentLinker,
+        },
+        Config, Engine, Store,
+    },
+};
+
+/// The parts of a Spin v1 manifest relevant to running an HTTP application; other fields are ignored
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub spin_manifest_version: String,
+    pub name: String,
+    pub trigger: AppTrigger,
+    /// Application variables, in the same form `ConfigResolver` expects
+    #[serde(default)]
+    pub variables: toml::Table,
+    #[serde(default)]
+    pub component: Vec<ComponentManifest>,
+}
+
+#[derive(Deserialize)]
+pub struct AppTrigger {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub base: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ComponentManifest {
+    pub id: String,
+    /// Path to the component's Wasm module, relative to the manifest
+    pub source: PathBuf,
+    pub trigger: ComponentTrigger,
+    #[serde(default)]
+    pub build: Option<BuildConfig>,
+    #[serde(default)]
+    pub allowed_http_hosts: Vec<String>,
+    /// Config key templates, which may refer to application variables
+    #[serde(default)]
+    pub config: toml::Table,
+}
+
+#[derive(Deserialize)]
+pub struct ComponentTrigger {
+    pub route: String,
+}
+
+#[derive(Deserialize)]
+pub struct BuildConfig {
+    /// Shell command which produces the component's `source`
+    pub command: String,
+    /// Directory to run `command` in, relative to the manifest; defaults to the manifest's directory
+    #[serde(default)]
+    pub workdir: Option<PathBuf>,
+}
+
+impl Manifest {
+    pub fn parse(toml: &str) -> Result<Self> {
+        let manifest = toml::from_str::<Self>(toml)?;
+
+        ensure!(
+            manifest.spin_manifest_version == "1",
+            "unsupported manifest version: {:?}",
+            manifest.spin_manifest_version
+        );
+
+        ensure!(
+            manifest.trigger.kind == "http",
+            "unsupported trigger type: {:?}",
+            manifest.trigger.kind
+        );
+
+        for (index, component) in manifest.component.iter().enumerate() {
+            if manifest.component[..index]
+                .iter()
+                .any(|other| other.id == component.id)
+            {
+                bail!("duplicate component id: {:?}", component.id);
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).with_context(|| path.display().to_string())
+    }
+}
+
+/// Run a component's build command, if it has one
+fn build_component(root: &Path, component: &ComponentManifest) -> Result<()> {
+    if let Some(build) = &component.build {
+        let workdir = build
+            .workdir
+            .as_ref()
+            .map_or_else(|| root.to_owned(), |workdir| root.join(workdir));
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&build.command)
+            .current_dir(&workdir)
+            .status()
+            .with_context(|| format!("unable to run build command for {:?}", component.id))?;
+
+        ensure!(
+            status.success(),
+            "build command for {:?} failed: {status}",
+            component.id
+        );
+    }
+
+    Ok(())
+}
+
+/// A component from an application manifest, pre-instantiated and ready to handle requests
+pub struct AppComponent {
+    pub id: String,
+    pub route: String,
+    pre: ComponentInstancePre<Host>,
+    config: Arc<ConfigResolver>,
+    http: Arc<OutboundHttp>,
+}
+
+impl AppComponent {
+    /// Create host state for a new instance of this component, with its config and allowed hosts applied
+    pub fn host(&self) -> Host {
+        Host {
+            config: self.config.clone(),
+            http: self.http.clone(),
+            ..stdio_host()
+        }
+    }
+
+    pub async fn instantiate(&self, store: &mut Store<Host>) -> Result<ComponentInstance> {
+        self.pre.instantiate_async(store).await
+    }
+}
+
+/// An application loaded from a manifest
+pub struct App {
+    engine: Engine,
+    components: Vec<AppComponent>,
+    /// Maps request paths to indexes into `components`
+    router: Router<usize>,
+}
+
+impl App {
+    /// Read the manifest at `manifest_path`, then run each of its components' build commands
+    ///
+    /// This is separate from `load` so that benchmarks can build once up front rather than each time they load
+    /// the app.
+    pub fn build(manifest_path: &Path) -> Result<()> {
+        let manifest = Manifest::read(manifest_path)?;
+        let root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+        for component in &manifest.component {
+            build_component(root, component)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the manifest at `manifest_path`, then componentize and pre-instantiate each of its (already built)
+    /// components using a single engine created from `config`
+    pub fn load(manifest_path: &Path, mut config: Config) -> Result<Self> {
+        let manifest = Manifest::read(manifest_path)?;
+        let root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+        config.async_support(true);
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config)?;
+        let mut linker = ComponentLinker::new(&engine);
+        add_to_linker(&mut linker)?;
+
+        let components = manifest
+            .component
+            .iter()
+            .map(|component| {
+                let source = root.join(&component.source);
+                let pre = linker.instantiate_pre(&Component::new(
+                    &engine,
+                    spin_componentize::componentize(
+                        &fs::read(&source).with_context(|| source.display().to_string())?,
+                    )?,
+                )?)?;
+
+                let mut schema = toml::Table::new();
+                schema.insert("variables".into(), manifest.variables.clone().into());
+                schema.insert("config".into(), component.config.clone().into());
+
+                let config = ConfigResolver::new(&toml::to_string(&schema)?)
+                    .with_context(|| format!("invalid config for {:?}", component.id))?
+                    .provider(EnvProvider::default());
+
+                let allowed_hosts = AllowedHosts::parse(
+                    &component
+                        .allowed_http_hosts
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                )?;
+
+                Ok(AppComponent {
+                    id: component.id.clone(),
+                    route: component.trigger.route.clone(),
+                    pre,
+                    config: Arc::new(config),
+                    http: Arc::new(OutboundHttp::new(allowed_hosts)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let router = Router::new(
+            manifest.trigger.base.as_deref().unwrap_or("/"),
+            components
+                .iter()
+                .enumerate()
+                .map(|(index, component)| (component.route.as_str(), index)),
+        )?;
+
+        Ok(Self {
+            engine,
+            components,
+            router,
+        })
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn components(&self) -> &[AppComponent] {
+        &self.components
+    }
+
+    /// Route the request to the matching component and pass it to a new instance of that component, returning a
+    /// 404 response if no route matches
+    pub async fn dispatch(&self, request: &IncomingRequest) -> Result<Response> {
+        let path = request
+            .uri
+            .split_once('?')
+            .map_or(request.uri.as_str(), |(path, _)| path);
+
+        let Some((&index, route)) = self.router.route(path) else {
+            return Ok(Response {
+                status: 404,
+                headers: None,
+                body: None,
+            });
+        };
+
+        let component = &self.components[index];
+        let mut store = Store::new(&self.engine, component.host());
+        let instance = component.instantiate(&mut store).await?;
+        let func = handle_request_func(&mut store, &instance)?;
+        call(&mut store, func, request, &route.params(), &mut ()).await
+    }
+}
+
+#[test]
+fn manifest_parsing() -> Result<()> {
+    let manifest = Manifest::read(&super::package_path("python-spin-guest/spin.toml"))?;
+    assert_eq!("spin-py-hello-world", manifest.name);
+    assert_eq!(Some("/"), manifest.trigger.base.as_deref());
+    assert_eq!(1, manifest.component.len());
+    assert_eq!("python-sdk-example", manifest.component[0].id);
+    assert_eq!(Path::new("app.wasm"), manifest.component[0].source);
+    assert_eq!("/...", manifest.component[0].trigger.route);
+    assert_eq!(
+        Some("spin py2wasm app -o app.wasm"),
+        manifest.component[0]
+            .build
+            .as_ref()
+            .map(|build| build.command.as_str())
+    );
+
+    let manifest = Manifest::read(&super::package_path("spin-app/spin.toml"))?;
+    assert!(manifest.component.len() > 1);
+
+    let app = |version: &str, trigger: &str, ids: &[&str]| {
+        let mut toml = format!(
+            "spin_manifest_version = {version:?}\nname = \"app\"\ntrigger = {{ type = {trigger:?} }}\n"
+        );
+        for id in ids {
+            toml.push_str(&format!(
+                "[[component]]\nid = {id:?}\nsource = \"{id}.wasm\"\ntrigger = {{ route = \"/{id}\" }}\n"
+            ));
+        }
+        Manifest::parse(&toml)
+    };
+
+    assert!(app("1", "http", &["a", "b"]).is_ok());
+    assert!(app("2", "http", &["a"]).is_err());
+    assert!(app("1", "redis", &["a"]).is_err());
+    assert!(app("1", "http", &["a", "a"]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn manifest_base_path() -> Result<()> {
+    super::compile_guests();
+
+    let dir = tempfile::tempdir()?;
+    let manifest_path = dir.path().join("spin.toml");
+    fs::write(
+        &manifest_path,
+        format!(
+            "spin_manifest_version = \"1\"\nname = \"app\"\ntrigger = {{ type = \"http\", base = \"/api\" }}\n\
+             [[component]]\nid = \"hello\"\nsource = {:?}\ntrigger = {{ route = \"/foo/...\" }}\n",
+            concat!(env!("OUT_DIR"), "/wasm32-wasi/release/spin_guest.wasm")
+        ),
+    )?;
+
+    let app = App::load(&manifest_path, Config::new())?;
+
+    // Routes are relative to the base path, so they only match with it
+    let (&index, route) = app
+        .router
+        .route("/api/foo/bar")
+        .ok_or_else(|| anyhow!("no route matched"))?;
+    assert_eq!("hello", app.components()[index].id);
+    assert_eq!("/api", route.base_path);
+    assert_eq!("/bar", route.path_info);
+    assert!(app.router.route("/foo/bar").is_none());
+
+    let response = tokio::runtime::Runtime::new()?.block_on(app.dispatch(&IncomingRequest {
+        method: super::Method::Get,
+        uri: "/foo/bar".to_owned(),
+        headers: Vec::new(),
+        body: None,
+    }))?;
+    assert_eq!(404, response.status);
+
+    Ok(())
+}
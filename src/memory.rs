@@ -0,0 +1,255 @@
+//! Memory footprint measurement: RSS, PSS, and committed memory per live instance, plus page faults per request
+//!
+//! Usage is read from `/proc/self`, so this is Linux-only.  Since the measurements are process-wide, the runner
+//! starts a new child process for each scenario.
+
+use {
+    super::{
+        compile_guests,
+        front_end::{Dispatcher, Strategy},
+        spin_test_instance, stdio_host, Host,
+    },
+    anyhow::{anyhow, Context, Error, Result},
+    serde::{Deserialize, Serialize},
+    std::{fs, mem},
+    tokio::runtime::Runtime,
+    wasmtime::Store,
+};
+
+/// A component and strategy to measure memory use for
+///
+/// The strategy must create an instance per request, so `Strategy::Reuse` isn't supported.
+pub struct MemoryScenario {
+    pub name: &'static str,
+    pub wasm_path: &'static str,
+    pub strategy: Strategy,
+}
+
+/// All memory scenarios, in the order the runner executes them
+pub const MEMORY_SCENARIOS: &[MemoryScenario] = &[
+    MemoryScenario {
+        name: "spin_rust_memory",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Fresh,
+    },
+    MemoryScenario {
+        name: "spin_rust_memory_pre_instance",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::PreInstance,
+    },
+    MemoryScenario {
+        name: "spin_rust_memory_pre_instance_with_pooling",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Pooling,
+    },
+    MemoryScenario {
+        name: "spin_python_memory_pre_instance",
+        wasm_path: "/python-spin-guest.wasm",
+        strategy: Strategy::PreInstance,
+    },
+];
+
+/// A snapshot of this process's memory usage and cumulative page faults
+#[derive(Serialize, Deserialize, Copy, Clone, Default, Debug)]
+pub struct Usage {
+    pub rss_bytes: u64,
+    pub pss_bytes: u64,
+    /// Total size of private, writable mappings, i.e. memory the kernel has committed to providing, as opposed to
+    /// address space which is merely reserved (e.g. Wasmtime's guard regions)
+    pub committed_bytes: u64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+}
+
+/// Find a line like "Rss:    1234 kB" and return its value in bytes
+fn kib_field(text: &str, name: &str) -> Result<u64> {
+    let line = text
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .ok_or_else(|| anyhow!("no {name} field found"))?;
+
+    let kib = line
+        .trim()
+        .strip_suffix("kB")
+        .ok_or_else(|| anyhow!("{name} field is not in kB: {line:?}"))?
+        .trim()
+        .parse::<u64>()?;
+
+    Ok(kib * 1024)
+}
+
+/// Sum the sizes of the private, writable mappings listed in `smaps`
+///
+/// This is roughly what the kernel counts towards its commit limit, and excludes `PROT_NONE` reservations such as
+/// Wasmtime's guard regions and the pooling allocator's unused slots.
+fn committed_bytes(smaps: &str) -> Result<u64> {
+    let mut total = 0;
+    let mut counted = false;
+
+    for line in smaps.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(first) = fields.next() else {
+            continue;
+        };
+
+        if !first.ends_with(':') {
+            // A mapping header, e.g. "7f0000000000-7f0000001000 rw-p 00000000 00:00 0"
+            counted = fields.next().map_or(false, |perms| {
+                perms.get(1..2) == Some("w") && perms.ends_with('p')
+            });
+        } else if counted && first == "Size:" {
+            total += kib_field(line, "Size")?;
+        }
+    }
+
+    Ok(total)
+}
+
+impl Usage {
+    pub fn current() -> Result<Self> {
+        let rollup =
+            fs::read_to_string("/proc/self/smaps_rollup").context("unable to read smaps_rollup")?;
+        let smaps = fs::read_to_string("/proc/self/smaps").context("unable to read smaps")?;
+
+        let mut usage = unsafe { mem::zeroed::<libc::rusage>() };
+        if -1 == unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } {
+            return Err(anyhow!("getrusage failed; errno: {}", errno::errno()));
+        }
+
+        Ok(Self {
+            rss_bytes: kib_field(&rollup, "Rss")?,
+            pss_bytes: kib_field(&rollup, "Pss")?,
+            committed_bytes: committed_bytes(&smaps)?,
+            minor_faults: usage.ru_minflt.try_into()?,
+            major_faults: usage.ru_majflt.try_into()?,
+        })
+    }
+
+    /// Return the change in each value since `earlier`
+    fn since(&self, earlier: &Self) -> Self {
+        Self {
+            rss_bytes: self.rss_bytes.saturating_sub(earlier.rss_bytes),
+            pss_bytes: self.pss_bytes.saturating_sub(earlier.pss_bytes),
+            committed_bytes: self.committed_bytes.saturating_sub(earlier.committed_bytes),
+            minor_faults: self.minor_faults.saturating_sub(earlier.minor_faults),
+            major_faults: self.major_faults.saturating_sub(earlier.major_faults),
+        }
+    }
+}
+
+/// The memory cost of a scenario
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Footprint {
+    /// Number of live instances (and requests) the per-instance figures are averaged over
+    pub instances: usize,
+    /// Memory used to create the engine and load the component, before any instances exist
+    pub setup: Usage,
+    pub rss_bytes_per_instance: f64,
+    pub pss_bytes_per_instance: f64,
+    pub committed_bytes_per_instance: f64,
+    /// Page faults per request when each instance is dropped after handling its request
+    pub minor_faults_per_request: f64,
+    pub major_faults_per_request: f64,
+}
+
+/// Create an instance and have it handle a request, returning its store (which keeps it alive)
+async fn request(dispatcher: &Dispatcher) -> Result<Store<Host>> {
+    let (mut store, instance) = dispatcher.instantiate().await?;
+    spin_test_instance(&mut store, &instance).await?;
+    Ok(store)
+}
+
+impl MemoryScenario {
+    /// Build the guests
+    ///
+    /// This should be called once by the parent process, before starting any child processes.
+    pub fn prepare() {
+        compile_guests();
+    }
+
+    /// Measure memory usage with `instances` live instances, then page faults over `instances` requests
+    ///
+    /// The guests must already have been built, and this should be the only thing the process does, since any
+    /// other allocations would skew the results.
+    pub fn measure(&self, instances: usize) -> Result<Footprint> {
+        let runtime = Runtime::new()?;
+
+        runtime.block_on(async {
+            let before = Usage::current()?;
+            let dispatcher = Dispatcher::new(self.wasm_path, self.strategy, stdio_host).await?;
+            let ready = Usage::current()?;
+
+            let mut live = Vec::with_capacity(instances);
+            for _ in 0..instances {
+                live.push(request(&dispatcher).await?);
+            }
+            let loaded = Usage::current()?.since(&ready);
+            drop(live);
+
+            let start = Usage::current()?;
+            for _ in 0..instances {
+                request(&dispatcher).await?;
+            }
+            let faults = Usage::current()?.since(&start);
+
+            let per_instance = |value: u64| value as f64 / instances.max(1) as f64;
+
+            Ok::<_, Error>(Footprint {
+                instances,
+                setup: ready.since(&before),
+                rss_bytes_per_instance: per_instance(loaded.rss_bytes),
+                pss_bytes_per_instance: per_instance(loaded.pss_bytes),
+                committed_bytes_per_instance: per_instance(loaded.committed_bytes),
+                minor_faults_per_request: per_instance(faults.minor_faults),
+                major_faults_per_request: per_instance(faults.major_faults),
+            })
+        })
+    }
+}
+
+#[test]
+fn memory_usage() -> Result<()> {
+    let rollup = "00400000-7ffc00021000 ---p 00000000 00:00 0 [rollup]\n\
+                  Rss:                4096 kB\n\
+                  Pss:                2048 kB\n";
+    assert_eq!(4096 * 1024, kib_field(rollup, "Rss")?);
+    assert_eq!(2048 * 1024, kib_field(rollup, "Pss")?);
+    assert!(kib_field(rollup, "Swap").is_err());
+    assert!(kib_field("Rss: 12 pages", "Rss").is_err());
+
+    let smaps = "\
+        00400000-00452000 r-xp 00000000 08:02 173521 /usr/bin/runner\n\
+        Size:                328 kB\n\
+        VmFlags: rd ex mr mw me dw\n\
+        7f0000000000-7f0000100000 rw-p 00000000 00:00 0\n\
+        Size:               1024 kB\n\
+        7f0000100000-7f0100100000 ---p 00000000 00:00 0\n\
+        Size:            4194304 kB\n\
+        7f0100100000-7f0100101000 rw-s 00000000 00:05 42 /dev/shm/shared\n\
+        Size:                  4 kB\n\
+        7ffc00000000-7ffc00021000 rw-p 00000000 00:00 0 [stack]\n\
+        Size:                132 kB\n";
+    assert_eq!((1024 + 132) * 1024, committed_bytes(smaps)?);
+
+    let before = Usage {
+        rss_bytes: 8 << 20,
+        pss_bytes: 4 << 20,
+        committed_bytes: 16 << 20,
+        minor_faults: 100,
+        major_faults: 1,
+    };
+    let after = Usage {
+        rss_bytes: 10 << 20,
+        committed_bytes: 12 << 20,
+        minor_faults: 150,
+        ..before
+    };
+    let delta = after.since(&before);
+    assert_eq!(2 << 20, delta.rss_bytes);
+    assert_eq!(0, delta.pss_bytes);
+    assert_eq!(0, delta.committed_bytes);
+    assert_eq!(50, delta.minor_faults);
+    assert_eq!(0, delta.major_faults);
+
+    Ok(())
+}
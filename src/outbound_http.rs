@@ -0,0 +1,309 @@
+//! Outbound HTTP support for the `http` interface, restricted by a Spin-style allowed-hosts list and optional rate
+//! limits
+
+use {
+    super::{
+        http,
+        rate_limit::{Limit, RateLimits},
+        HttpError, Method, RequestResult, Response,
+    },
+    anyhow::{anyhow, bail, Result},
+    async_trait::async_trait,
+    hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Server,
+    },
+    reqwest::{
+        header::{HeaderMap, HeaderName, HeaderValue},
+        Client, Url,
+    },
+    std::{convert::Infallible, net::SocketAddr},
+    tokio::task::JoinHandle,
+};
+
+/// An entry in an allowed-hosts list, e.g. "https://example.com" or "http://127.0.0.1:3000"
+pub struct AllowedHost {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+}
+
+impl AllowedHost {
+    fn parse(entry: &str) -> Result<Self> {
+        let url = Url::parse(entry)?;
+        if url.path() != "/" || url.query().is_some() {
+            bail!("allowed host {entry:?} must not include a path or query");
+        }
+
+        Ok(Self {
+            scheme: url.scheme().to_owned(),
+            host: url
+                .host_str()
+                .ok_or_else(|| anyhow!("allowed host {entry:?} has no host"))?
+                .to_owned(),
+            port: url.port_or_known_default(),
+        })
+    }
+
+    fn allows(&self, url: &Url) -> bool {
+        self.scheme == url.scheme()
+            && Some(self.host.as_str()) == url.host_str()
+            && self.port == url.port_or_known_default()
+    }
+}
+
+/// The destinations a component may send requests to
+pub enum AllowedHosts {
+    /// Equivalent to Spin's "insecure:allow-all"
+    All,
+    Only(Vec<AllowedHost>),
+}
+
+impl Default for AllowedHosts {
+    /// By default, nothing is allowed, as in Spin
+    fn default() -> Self {
+        Self::Only(Vec::new())
+    }
+}
+
+impl AllowedHosts {
+    /// Parse a list of entries as they would appear in a Spin manifest's `allowed_http_hosts` field
+    pub fn parse(entries: &[&str]) -> Result<Self> {
+        if entries.contains(&"insecure:allow-all") {
+            Ok(Self::All)
+        } else {
+            Ok(Self::Only(
+                entries
+                    .iter()
+                    .map(|entry| AllowedHost::parse(entry))
+                    .collect::<Result<_>>()?,
+            ))
+        }
+    }
+
+    fn allows(&self, url: &Url) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(hosts) => hosts.iter().any(|host| host.allows(url)),
+        }
+    }
+}
+
+/// Per-component outbound HTTP configuration
+#[derive(Default)]
+pub struct OutboundHttp {
+    client: Client,
+    allowed_hosts: AllowedHosts,
+    limits: RateLimits,
+}
+
+impl OutboundHttp {
+    pub fn new(allowed_hosts: AllowedHosts) -> Self {
+        Self {
+            client: Client::new(),
+            allowed_hosts,
+            limits: RateLimits::default(),
+        }
+    }
+
+    /// Reject requests with `too-many-requests` when they would exceed the specified limits
+    pub fn limits(mut self, limits: RateLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub async fn send(&self, request: RequestResult) -> Result<Response, HttpError> {
+        let url = Url::parse(&request.uri).map_err(|_| HttpError::InvalidUrl)?;
+
+        if !self.allowed_hosts.allows(&url) {
+            return Err(HttpError::DestinationNotAllowed);
+        }
+
+        let origin = url.origin().ascii_serialization();
+
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Put => reqwest::Method::PUT,
+            Method::Delete => reqwest::Method::DELETE,
+            Method::Patch => reqwest::Method::PATCH,
+            Method::Head => reqwest::Method::HEAD,
+            Method::Options => reqwest::Method::OPTIONS,
+        };
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &request.headers {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| HttpError::RequestError)?,
+                HeaderValue::from_str(value).map_err(|_| HttpError::RequestError)?,
+            );
+        }
+
+        let mut builder = self.client.request(method, url).headers(headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        // Taken only once the request is known to be valid, and held until the response body has been received, so
+        // that slow responses count against in-flight caps
+        let _permits = self
+            .limits
+            .try_acquire(&origin)
+            .ok_or(HttpError::TooManyRequests)?;
+
+        let response = builder.send().await.map_err(|_| HttpError::RequestError)?;
+
+        let status = response.status().as_u16();
+
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    name.as_str().to_owned(),
+                    value
+                        .to_str()
+                        .map_err(|_| HttpError::RuntimeError)?
+                        .to_owned(),
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|_| HttpError::RequestError)?
+            .to_vec();
+
+        Ok(Response {
+            status,
+            headers: Some(headers),
+            body: Some(body),
+        })
+    }
+}
+
+#[async_trait]
+impl http::Host for super::Host {
+    async fn send_request(&mut self, req: RequestResult) -> Result<Result<Response, HttpError>> {
+        Ok(self.http.send(req).await)
+    }
+}
+
+/// Loopback HTTP server which answers every request with "hola, mundo!", for guests to fan out to
+///
+/// The server runs as a task on the current Tokio runtime and is stopped when this handle is dropped.
+pub struct Backend {
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl Backend {
+    pub fn start() -> Result<Self> {
+        let server =
+            Server::try_bind(&([127, 0, 0, 1], 0).into())?.serve(make_service_fn(|_| async {
+                Ok::<_, Infallible>(service_fn(|_| async {
+                    Ok::<_, Infallible>(
+                        hyper::Response::builder()
+                            .header("content-type", "text/plain")
+                            .body(Body::from("hola, mundo!"))
+                            .unwrap(),
+                    )
+                }))
+            }));
+
+        let address = server.local_addr();
+
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("backend server error: {e:?}");
+            }
+        });
+
+        Ok(Self { address, task })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.address)
+    }
+}
+
+impl Drop for Backend {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+fn get(uri: &str) -> RequestResult {
+    RequestResult {
+        method: Method::Get,
+        uri: uri.to_owned(),
+        headers: vec![("what".to_owned(), "up".to_owned())],
+        params: Vec::new(),
+        body: None,
+    }
+}
+
+#[tokio::test]
+async fn outbound_http_policy() -> Result<()> {
+    let backend = Backend::start()?;
+
+    let http = OutboundHttp::new(AllowedHosts::parse(&[&backend.url("")])?);
+
+    let response = http.send(get(&backend.url("/hola"))).await.unwrap();
+    assert_eq!(200, response.status);
+    assert_eq!(Some(b"hola, mundo!" as &[_]), response.body.as_deref());
+
+    assert!(matches!(
+        http.send(get("http://example.com/")).await,
+        Err(HttpError::DestinationNotAllowed)
+    ));
+    assert!(matches!(
+        http.send(get("not a url")).await,
+        Err(HttpError::InvalidUrl)
+    ));
+    assert!(matches!(
+        OutboundHttp::default().send(get(&backend.url("/"))).await,
+        Err(HttpError::DestinationNotAllowed)
+    ));
+
+    // Nothing is listening on a port we just released, so this should fail to connect:
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let http = OutboundHttp::new(AllowedHosts::All);
+    assert!(matches!(
+        http.send(get(&format!("http://{closed}/"))).await,
+        Err(HttpError::RequestError)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn outbound_http_limits() -> Result<()> {
+    let backend = Backend::start()?;
+
+    let http =
+        OutboundHttp::new(AllowedHosts::All).limits(RateLimits::default().per_destination(Limit {
+            rate: Some((1, 0.0)),
+            max_in_flight: None,
+        }));
+
+    // An invalid request should be rejected without using up the destination's only token:
+    let mut invalid = get(&backend.url("/"));
+    invalid
+        .headers
+        .push(("bad header".to_owned(), "value".to_owned()));
+    assert!(matches!(
+        http.send(invalid).await,
+        Err(HttpError::RequestError)
+    ));
+
+    assert!(http.send(get(&backend.url("/"))).await.is_ok());
+    assert!(matches!(
+        http.send(get(&backend.url("/"))).await,
+        Err(HttpError::TooManyRequests)
+    ));
+
+    Ok(())
+}
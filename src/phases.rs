@@ -0,0 +1,274 @@
+//! Per-phase timing of the Wasm request lifecycle: waiting for the shared instance (for the reuse strategy only),
+//! building the host (including `WasiCtxBuilder::build`), creating the `Store`, compiling (for the fresh strategy
+//! only), instantiating, looking up the `handle-request` export, calling it, `post_return`, and dropping the store
+//!
+//! Each phase is timed on every iteration and recorded in its own histogram, so we can see which phase dominates for
+//! each strategy.  Timing each phase separately adds a few `Instant::now` calls per request, so the totals are
+//! slightly higher than those of the corresponding latency scenarios.
+
+use {
+    super::{
+        bencher::{new_histogram, Options, Summary},
+        check_test_response, compile_guests,
+        front_end::{Dispatcher, IncomingRequest, Strategy},
+        stdio_host, test_request,
+    },
+    anyhow::{Error, Result},
+    hdrhistogram::Histogram,
+    std::time::Instant,
+    tokio::runtime::Runtime,
+};
+
+/// A phase of handling a request
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Locking the shared store and instance, for the reuse strategy only
+    Lock,
+    /// Building the host state, including its `WasiCtx`
+    Host,
+    /// `Store::new`
+    Store,
+    /// `Component::new`, for the fresh strategy only
+    Compile,
+    /// Instantiating the component, including any linking the strategy leaves until then
+    Instantiate,
+    /// Finding the `handle-request` export in the `inbound-http` instance
+    Lookup,
+    /// Type-checking and calling `handle-request`, including lowering the request and lifting the response
+    Call,
+    /// `post_return_async`
+    PostReturn,
+    /// Dropping the store, and with it the instance
+    Drop,
+    /// The sum of all the above
+    Total,
+}
+
+/// All phases, in lifecycle order (which is also the order of their discriminants)
+pub const PHASES: &[Phase] = &[
+    Phase::Lock,
+    Phase::Host,
+    Phase::Store,
+    Phase::Compile,
+    Phase::Instantiate,
+    Phase::Lookup,
+    Phase::Call,
+    Phase::PostReturn,
+    Phase::Drop,
+    Phase::Total,
+];
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lock => "lock",
+            Self::Host => "host",
+            Self::Store => "store",
+            Self::Compile => "compile",
+            Self::Instantiate => "instantiate",
+            Self::Lookup => "lookup",
+            Self::Call => "call",
+            Self::PostReturn => "post_return",
+            Self::Drop => "drop",
+            Self::Total => "total",
+        }
+    }
+}
+
+/// Latency histograms for each phase, aggregated across iterations
+pub struct Phases {
+    histograms: Vec<Histogram<u64>>,
+}
+
+impl Phases {
+    fn new() -> Self {
+        Self {
+            histograms: PHASES.iter().map(|_| new_histogram()).collect(),
+        }
+    }
+
+    fn record(&mut self, phase: Phase, nanos: u64) {
+        self.histograms[phase as usize].saturating_record(nanos);
+    }
+
+    fn reset(&mut self) {
+        for histogram in &mut self.histograms {
+            histogram.reset();
+        }
+    }
+
+    /// Number of iterations recorded
+    pub fn iterations(&self) -> u64 {
+        self.histograms[Phase::Total as usize].len()
+    }
+
+    /// Summarize each phase the strategy went through, in lifecycle order
+    ///
+    /// Phases a strategy skips (e.g. everything but the lock, the call, and `post_return` when reusing an instance)
+    /// are omitted.
+    pub fn summarize(&self) -> Vec<(Phase, Summary)> {
+        PHASES
+            .iter()
+            .zip(&self.histograms)
+            .filter(|(_, histogram)| !histogram.is_empty())
+            .map(|(&phase, histogram)| (phase, Summary::from_histogram(histogram)))
+            .collect()
+    }
+}
+
+/// Told by `front_end::Dispatcher::dispatch_with` and `front_end::call` as each phase of a request completes
+pub trait Timer {
+    fn lap(&mut self, phase: Phase);
+}
+
+/// Does nothing, for callers which don't care about phases
+impl Timer for () {
+    fn lap(&mut self, _phase: Phase) {}
+}
+
+/// Records the time since the previous lap as a phase of a single request
+struct Laps<'a> {
+    phases: &'a mut Phases,
+    since: Instant,
+    total: u64,
+}
+
+impl<'a> Laps<'a> {
+    fn start(phases: &'a mut Phases) -> Self {
+        Self {
+            phases,
+            since: Instant::now(),
+            total: 0,
+        }
+    }
+
+    fn finish(self) {
+        self.phases.record(Phase::Total, self.total);
+    }
+}
+
+impl Timer for Laps<'_> {
+    /// Record the time elapsed since the previous lap as `phase`
+    fn lap(&mut self, phase: Phase) {
+        let now = Instant::now();
+        let nanos = now
+            .duration_since(self.since)
+            .as_nanos()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        self.phases.record(phase, nanos);
+        self.total = self.total.saturating_add(nanos);
+        self.since = now;
+    }
+}
+
+/// A component and strategy to break down into phases
+pub struct PhaseScenario {
+    pub name: &'static str,
+    pub wasm_path: &'static str,
+    pub strategy: Strategy,
+}
+
+/// All phase scenarios, in the order the runner executes them
+pub const PHASE_SCENARIOS: &[PhaseScenario] = &[
+    PhaseScenario {
+        name: "spin_rust_phases",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Fresh,
+    },
+    PhaseScenario {
+        name: "spin_rust_phases_pre_instance",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::PreInstance,
+    },
+    PhaseScenario {
+        name: "spin_rust_phases_pre_instance_with_pooling",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Pooling,
+    },
+    PhaseScenario {
+        name: "spin_rust_phases_reuse_instance",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Reuse,
+    },
+    PhaseScenario {
+        name: "spin_python_phases_pre_instance",
+        wasm_path: "/python-spin-guest.wasm",
+        strategy: Strategy::PreInstance,
+    },
+];
+
+/// Handle `request`, recording how long each phase took
+async fn request(
+    dispatcher: &Dispatcher,
+    request: &IncomingRequest,
+    phases: &mut Phases,
+) -> Result<()> {
+    let mut laps = Laps::start(phases);
+    let response = dispatcher.dispatch_with(request, &mut laps).await?;
+    laps.finish();
+
+    check_test_response(response);
+
+    Ok(())
+}
+
+impl PhaseScenario {
+    /// Time each phase of handling requests, warming up and then recording for as long as `options` specifies
+    pub fn measure(&self, options: Options) -> Result<Phases> {
+        compile_guests();
+
+        let runtime = Runtime::new()?;
+
+        runtime.block_on(async {
+            let dispatcher = Dispatcher::new(self.wasm_path, self.strategy, stdio_host).await?;
+            let test_request = test_request();
+            let mut phases = Phases::new();
+
+            let start = Instant::now();
+            while start.elapsed() < options.warm_up {
+                request(&dispatcher, &test_request, &mut phases).await?;
+            }
+
+            phases.reset();
+
+            let start = Instant::now();
+            while start.elapsed() < options.budget || phases.iterations() < options.min_iterations {
+                request(&dispatcher, &test_request, &mut phases).await?;
+            }
+
+            Ok::<_, Error>(phases)
+        })
+    }
+}
+
+#[test]
+fn phase_laps() {
+    for (index, &phase) in PHASES.iter().enumerate() {
+        assert_eq!(index, phase as usize, "{}", phase.name());
+    }
+
+    let mut phases = Phases::new();
+    for _ in 0..3 {
+        let mut laps = Laps::start(&mut phases);
+        laps.lap(Phase::Host);
+        laps.lap(Phase::Call);
+        laps.finish();
+    }
+
+    assert_eq!(3, phases.iterations());
+
+    let summaries = phases.summarize();
+    assert_eq!(
+        vec![Phase::Host, Phase::Call, Phase::Total],
+        summaries
+            .iter()
+            .map(|(phase, _)| *phase)
+            .collect::<Vec<_>>()
+    );
+    assert!(summaries.iter().all(|(_, summary)| summary.iterations == 3));
+
+    phases.reset();
+    assert_eq!(0, phases.iterations());
+    assert!(phases.summarize().is_empty());
+}
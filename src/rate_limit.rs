@@ -0,0 +1,225 @@
+//! Token-bucket rate limits and in-flight caps for outbound requests
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// Limits to apply to a stream of requests; `None` means unlimited
+#[derive(Copy, Clone, Default)]
+pub struct Limit {
+    /// Bucket capacity (i.e. maximum burst size) and the number of tokens added per second
+    pub rate: Option<(u32, f64)>,
+
+    /// Maximum number of requests which may be in flight at once
+    pub max_in_flight: Option<usize>,
+}
+
+struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32, per_second: f64) -> Self {
+        Self {
+            capacity: capacity.into(),
+            per_second,
+            state: Mutex::new((capacity.into(), Instant::now())),
+        }
+    }
+
+    fn try_take(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.per_second)
+            .min(self.capacity);
+        *last = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return a token taken by `try_take`, e.g. because the request it was taken for was never sent
+    fn put_back(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = (state.0 + 1.0).min(self.capacity);
+    }
+}
+
+/// Enforces a `Limit` for one stream of requests
+pub struct Limiter {
+    bucket: Option<TokenBucket>,
+    max_in_flight: Option<usize>,
+    in_flight: AtomicUsize,
+}
+
+impl Limiter {
+    pub fn new(limit: Limit) -> Self {
+        Self {
+            bucket: limit
+                .rate
+                .map(|(capacity, per_second)| TokenBucket::new(capacity, per_second)),
+            max_in_flight: limit.max_in_flight,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Attempt to start a request, returning `None` if doing so would exceed the limit
+    ///
+    /// The request counts as in flight until the returned `Permit` is dropped.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let in_flight = self.in_flight.fetch_add(1, SeqCst);
+        let permit = Permit(self.clone());
+
+        if self.max_in_flight.map_or(false, |max| in_flight >= max) {
+            return None;
+        }
+
+        if let Some(bucket) = &self.bucket {
+            if !bucket.try_take() {
+                return None;
+            }
+        }
+
+        Some(permit)
+    }
+}
+
+/// Represents a request in flight, counted against a `Limiter` until dropped
+pub struct Permit(Arc<Limiter>);
+
+impl Permit {
+    /// Give up the permit without having made the request, returning its token to the bucket
+    fn refund(self) {
+        if let Some(bucket) = &self.0.bucket {
+            bucket.put_back();
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, SeqCst);
+    }
+}
+
+/// Per-component and per-destination limits for outbound requests
+#[derive(Default)]
+pub struct RateLimits {
+    component: Option<Arc<Limiter>>,
+    per_destination: Option<Limit>,
+    destinations: Mutex<HashMap<String, Arc<Limiter>>>,
+}
+
+impl RateLimits {
+    /// Limit all requests made by the component, regardless of destination
+    pub fn component(mut self, limit: Limit) -> Self {
+        self.component = Some(Arc::new(Limiter::new(limit)));
+        self
+    }
+
+    /// Limit requests made by the component to each destination (i.e. scheme, host, and port) separately
+    pub fn per_destination(mut self, limit: Limit) -> Self {
+        self.per_destination = Some(limit);
+        self
+    }
+
+    /// Attempt to start a request to `destination`, returning `None` if doing so would exceed any limit
+    ///
+    /// A request which is rejected doesn't use up any tokens, so e.g. a throttled destination can't drain the
+    /// component-wide budget.
+    pub fn try_acquire(&self, destination: &str) -> Option<Vec<Permit>> {
+        let mut permits = Vec::with_capacity(2);
+
+        if let Some(limiter) = &self.component {
+            permits.push(limiter.try_acquire()?);
+        }
+
+        if let Some(limit) = self.per_destination {
+            let limiter = self
+                .destinations
+                .lock()
+                .unwrap()
+                .entry(destination.to_owned())
+                .or_insert_with(|| Arc::new(Limiter::new(limit)))
+                .clone();
+
+            match limiter.try_acquire() {
+                Some(permit) => permits.push(permit),
+                None => {
+                    for permit in permits {
+                        permit.refund();
+                    }
+                    return None;
+                }
+            }
+        }
+
+        Some(permits)
+    }
+}
+
+#[test]
+fn rate_limits() {
+    let limits = RateLimits::default()
+        .component(Limit {
+            rate: None,
+            max_in_flight: Some(2),
+        })
+        .per_destination(Limit {
+            rate: Some((2, 0.0)),
+            max_in_flight: None,
+        });
+
+    // Each destination gets a burst of two requests, and the bucket never refills:
+    drop(limits.try_acquire("http://a").unwrap());
+    drop(limits.try_acquire("http://a").unwrap());
+    assert!(limits.try_acquire("http://a").is_none());
+
+    // ...but other destinations are unaffected, up to the component-wide in-flight cap:
+    let b = limits.try_acquire("http://b").unwrap();
+    let c = limits.try_acquire("http://c").unwrap();
+    assert!(limits.try_acquire("http://d").is_none());
+    drop(b);
+    drop(c);
+    assert!(limits.try_acquire("http://d").is_some());
+
+    // A destination which rejects a request doesn't use up the component's tokens:
+    let limits = RateLimits::default()
+        .component(Limit {
+            rate: Some((2, 0.0)),
+            max_in_flight: None,
+        })
+        .per_destination(Limit {
+            rate: None,
+            max_in_flight: Some(1),
+        });
+    let a = limits.try_acquire("http://a").unwrap();
+    for _ in 0..10 {
+        assert!(limits.try_acquire("http://a").is_none());
+    }
+    drop(a);
+    assert!(limits.try_acquire("http://b").is_some());
+    assert!(limits.try_acquire("http://c").is_none());
+
+    // Buckets refill over time:
+    let limiter = Arc::new(Limiter::new(Limit {
+        rate: Some((1, 1000.0)),
+        max_in_flight: None,
+    }));
+    assert!(limiter.try_acquire().is_some());
+    assert!(limiter.try_acquire().is_none());
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    assert!(limiter.try_acquire().is_some());
+}
@@ -0,0 +1,494 @@
+//! SQLite-backed implementations of the `postgres` and `mysql` interfaces
+//!
+//! Each distinct `address` gets its own in-memory database, which lives as long as the `SqlEngine`.  Statements
+//! are passed to SQLite mostly as-is, except that PostgreSQL-style `$N` placeholders are rewritten to SQLite's
+//! equivalent `?N` syntax.
+
+use {
+    super::{
+        mysql, postgres,
+        rdbms_types::{Column, DbDataType, DbValue, ParameterValue, RowSet},
+        Host,
+    },
+    anyhow::Result,
+    async_trait::async_trait,
+    rusqlite::{types::Value, Connection},
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Errors common to both interfaces, converted to `pg-error` or `mysql-error` as appropriate
+#[derive(Debug)]
+pub enum SqlError {
+    ConnectionFailed(String),
+    BadParameter(String),
+    QueryFailed(String),
+    ValueConversionFailed(String),
+}
+
+impl From<SqlError> for postgres::PgError {
+    fn from(error: SqlError) -> Self {
+        match error {
+            SqlError::ConnectionFailed(e) => Self::ConnectionFailed(e),
+            SqlError::BadParameter(e) => Self::BadParameter(e),
+            SqlError::QueryFailed(e) => Self::QueryFailed(e),
+            SqlError::ValueConversionFailed(e) => Self::ValueConversionFailed(e),
+        }
+    }
+}
+
+impl From<SqlError> for mysql::MysqlError {
+    fn from(error: SqlError) -> Self {
+        match error {
+            SqlError::ConnectionFailed(e) => Self::ConnectionFailed(e),
+            SqlError::BadParameter(e) => Self::BadParameter(e),
+            SqlError::QueryFailed(e) => Self::QueryFailed(e),
+            SqlError::ValueConversionFailed(e) => Self::ValueConversionFailed(e),
+        }
+    }
+}
+
+/// The SQL dialect a statement was written for, determined by which interface the guest called
+#[derive(Copy, Clone)]
+enum Dialect {
+    Postgres,
+    Mysql,
+}
+
+impl Dialect {
+    fn schemes(self) -> &'static [&'static str] {
+        match self {
+            Self::Postgres => &["postgres://", "postgresql://"],
+            Self::Mysql => &["mysql://"],
+        }
+    }
+
+    /// Rewrite `statement` so SQLite can parse it
+    fn translate(self, statement: &str) -> String {
+        match self {
+            Self::Mysql => statement.to_owned(),
+            Self::Postgres => {
+                // Rewrite `$N` to `?N`, leaving quoted strings and identifiers alone
+                let mut translated = String::with_capacity(statement.len());
+                let mut quote = None;
+                let mut chars = statement.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match (quote, c) {
+                        (None, '\'' | '"') => quote = Some(c),
+                        (Some(q), _) if q == c => quote = None,
+                        (None, '$') if chars.peek().map_or(false, char::is_ascii_digit) => {
+                            translated.push('?');
+                            continue;
+                        }
+                        _ => (),
+                    }
+                    translated.push(c);
+                }
+                translated
+            }
+        }
+    }
+}
+
+/// A set of in-memory SQLite databases, indexed by address
+#[derive(Default)]
+pub struct SqlEngine {
+    databases: Mutex<HashMap<String, Arc<Mutex<Connection>>>>,
+}
+
+impl SqlEngine {
+    fn connect(&self, dialect: Dialect, address: &str) -> Result<Arc<Mutex<Connection>>, SqlError> {
+        if !dialect
+            .schemes()
+            .iter()
+            .any(|scheme| address.starts_with(scheme))
+        {
+            return Err(SqlError::ConnectionFailed(format!(
+                "unsupported address {address:?}; expected one of {:?}",
+                dialect.schemes()
+            )));
+        }
+
+        let mut databases = self.databases.lock().unwrap();
+        if let Some(connection) = databases.get(address) {
+            Ok(connection.clone())
+        } else {
+            let connection = Arc::new(Mutex::new(
+                Connection::open_in_memory()
+                    .map_err(|e| SqlError::ConnectionFailed(e.to_string()))?,
+            ));
+            databases.insert(address.to_owned(), connection.clone());
+            Ok(connection)
+        }
+    }
+
+    fn execute(
+        &self,
+        dialect: Dialect,
+        address: &str,
+        statement: &str,
+        params: Vec<ParameterValue>,
+    ) -> Result<u64, SqlError> {
+        let connection = self.connect(dialect, address)?;
+        let connection = connection.lock().unwrap();
+        let params = convert_params(params)?;
+
+        let count = connection
+            .prepare_cached(&dialect.translate(statement))
+            .and_then(|mut statement| statement.execute(rusqlite::params_from_iter(params.iter())))
+            .map_err(|e| SqlError::QueryFailed(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    fn query(
+        &self,
+        dialect: Dialect,
+        address: &str,
+        statement: &str,
+        params: Vec<ParameterValue>,
+    ) -> Result<RowSet, SqlError> {
+        let connection = self.connect(dialect, address)?;
+        let connection = connection.lock().unwrap();
+        let params = convert_params(params)?;
+
+        let mut statement = connection
+            .prepare_cached(&dialect.translate(statement))
+            .map_err(|e| SqlError::QueryFailed(e.to_string()))?;
+
+        let declared = statement
+            .columns()
+            .iter()
+            .map(|column| {
+                (
+                    column.name().to_owned(),
+                    column.decl_type().map(declared_type),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut raw_rows = Vec::new();
+        let mut rows = statement
+            .query(rusqlite::params_from_iter(params.iter()))
+            .map_err(|e| SqlError::QueryFailed(e.to_string()))?;
+
+        while let Some(row) = rows
+            .next()
+            .map_err(|e| SqlError::QueryFailed(e.to_string()))?
+        {
+            raw_rows.push(
+                (0..declared.len())
+                    .map(|index| row.get_ref(index).map(Value::from))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                    .map_err(|e| SqlError::QueryFailed(e.to_string()))?,
+            );
+        }
+
+        // Columns without a declared type (e.g. expressions) take the type of their first non-null value
+        let columns = declared
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, data_type))| Column {
+                name,
+                data_type: data_type.unwrap_or_else(|| {
+                    raw_rows
+                        .iter()
+                        .find_map(|row| inferred_type(&row[index]))
+                        .unwrap_or(DbDataType::Other)
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        let rows = raw_rows
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .zip(&columns)
+                    .map(|(value, column)| convert_value(value, column))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(RowSet { columns, rows })
+    }
+}
+
+fn convert_params(params: Vec<ParameterValue>) -> Result<Vec<Value>, SqlError> {
+    params
+        .into_iter()
+        .map(|param| {
+            Ok(match param {
+                ParameterValue::Boolean(v) => Value::Integer(v.into()),
+                ParameterValue::Int8(v) => Value::Integer(v.into()),
+                ParameterValue::Int16(v) => Value::Integer(v.into()),
+                ParameterValue::Int32(v) => Value::Integer(v.into()),
+                ParameterValue::Int64(v) => Value::Integer(v),
+                ParameterValue::Uint8(v) => Value::Integer(v.into()),
+                ParameterValue::Uint16(v) => Value::Integer(v.into()),
+                ParameterValue::Uint32(v) => Value::Integer(v.into()),
+                ParameterValue::Uint64(v) => Value::Integer(i64::try_from(v).map_err(|_| {
+                    SqlError::BadParameter(format!("{v} is too large to be stored as an integer"))
+                })?),
+                ParameterValue::Floating32(v) => Value::Real(v.into()),
+                ParameterValue::Floating64(v) => Value::Real(v),
+                ParameterValue::Str(v) => Value::Text(v),
+                ParameterValue::Binary(v) => Value::Blob(v),
+                ParameterValue::DbNull => Value::Null,
+            })
+        })
+        .collect()
+}
+
+/// Map a declared column type to a `db-data-type` using the same affinity rules as PostgreSQL and MySQL would
+fn declared_type(declared: &str) -> DbDataType {
+    let declared = declared.to_ascii_uppercase();
+    let unsigned = declared.contains("UNSIGNED");
+    let base = declared
+        .split(|c: char| c == '(' || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+
+    match (base, unsigned) {
+        ("BOOL" | "BOOLEAN", _) => DbDataType::Boolean,
+        ("TINYINT", false) => DbDataType::Int8,
+        ("TINYINT", true) => DbDataType::Uint8,
+        ("SMALLINT" | "INT2", false) => DbDataType::Int16,
+        ("SMALLINT", true) => DbDataType::Uint16,
+        ("INT" | "INTEGER" | "INT4" | "MEDIUMINT", false) => DbDataType::Int32,
+        ("INT" | "INTEGER" | "MEDIUMINT", true) => DbDataType::Uint32,
+        ("BIGINT" | "INT8", false) => DbDataType::Int64,
+        ("BIGINT", true) => DbDataType::Uint64,
+        ("REAL" | "FLOAT4", _) => DbDataType::Floating32,
+        ("DOUBLE" | "FLOAT" | "FLOAT8" | "NUMERIC" | "DECIMAL", _) => DbDataType::Floating64,
+        ("TEXT" | "VARCHAR" | "CHAR" | "CHARACTER" | "STRING", _) => DbDataType::Str,
+        ("BLOB" | "BYTEA" | "BINARY" | "VARBINARY", _) => DbDataType::Binary,
+        _ => DbDataType::Other,
+    }
+}
+
+fn inferred_type(value: &Value) -> Option<DbDataType> {
+    match value {
+        Value::Null => None,
+        Value::Integer(_) => Some(DbDataType::Int64),
+        Value::Real(_) => Some(DbDataType::Floating64),
+        Value::Text(_) => Some(DbDataType::Str),
+        Value::Blob(_) => Some(DbDataType::Binary),
+    }
+}
+
+fn convert_value(value: Value, column: &Column) -> Result<DbValue, SqlError> {
+    let mismatch = |value: &Value| {
+        SqlError::ValueConversionFailed(format!(
+            "unable to convert {value:?} to {:?} for column {:?}",
+            column.data_type, column.name
+        ))
+    };
+
+    fn int<T: TryFrom<i64>>(value: &Value) -> Option<T> {
+        match value {
+            Value::Integer(v) => T::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    Ok(match (column.data_type, &value) {
+        (_, Value::Null) => DbValue::DbNull,
+        (DbDataType::Boolean, Value::Integer(v)) => DbValue::Boolean(*v != 0),
+        (DbDataType::Int8, _) => DbValue::Int8(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Int16, _) => DbValue::Int16(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Int32, _) => DbValue::Int32(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Int64, _) => DbValue::Int64(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Uint8, _) => DbValue::Uint8(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Uint16, _) => DbValue::Uint16(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Uint32, _) => DbValue::Uint32(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Uint64, _) => DbValue::Uint64(int(&value).ok_or_else(|| mismatch(&value))?),
+        (DbDataType::Floating32, Value::Real(v)) => DbValue::Floating32(*v as f32),
+        (DbDataType::Floating32, Value::Integer(v)) => DbValue::Floating32(*v as f32),
+        (DbDataType::Floating64, Value::Real(v)) => DbValue::Floating64(*v),
+        (DbDataType::Floating64, Value::Integer(v)) => DbValue::Floating64(*v as f64),
+        (DbDataType::Str | DbDataType::Other, Value::Text(_)) => {
+            let Value::Text(v) = value else {
+                unreachable!()
+            };
+            DbValue::Str(v)
+        }
+        (DbDataType::Binary | DbDataType::Other, Value::Blob(_)) => {
+            let Value::Blob(v) = value else {
+                unreachable!()
+            };
+            DbValue::Binary(v)
+        }
+        (DbDataType::Other, Value::Integer(v)) => DbValue::Int64(*v),
+        (DbDataType::Other, Value::Real(v)) => DbValue::Floating64(*v),
+        _ => return Err(mismatch(&value)),
+    })
+}
+
+#[async_trait]
+impl postgres::Host for Host {
+    async fn query(
+        &mut self,
+        address: String,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<Result<RowSet, postgres::PgError>> {
+        Ok(self
+            .sql
+            .query(Dialect::Postgres, &address, &statement, params)
+            .map_err(From::from))
+    }
+
+    async fn execute(
+        &mut self,
+        address: String,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<Result<u64, postgres::PgError>> {
+        Ok(self
+            .sql
+            .execute(Dialect::Postgres, &address, &statement, params)
+            .map_err(From::from))
+    }
+}
+
+#[async_trait]
+impl mysql::Host for Host {
+    async fn query(
+        &mut self,
+        address: String,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<Result<RowSet, mysql::MysqlError>> {
+        Ok(self
+            .sql
+            .query(Dialect::Mysql, &address, &statement, params)
+            .map_err(From::from))
+    }
+
+    async fn execute(
+        &mut self,
+        address: String,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<Result<(), mysql::MysqlError>> {
+        Ok(self
+            .sql
+            .execute(Dialect::Mysql, &address, &statement, params)
+            .map(drop)
+            .map_err(From::from))
+    }
+}
+
+#[test]
+fn sql_round_trip() {
+    let engine = SqlEngine::default();
+    let pg = "postgres://localhost/test";
+
+    engine
+        .execute(
+            Dialect::Postgres,
+            pg,
+            "CREATE TABLE t (id BIGINT PRIMARY KEY, small SMALLINT, flag BOOLEAN, name TEXT, data BYTEA)",
+            Vec::new(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        1,
+        engine
+            .execute(
+                Dialect::Postgres,
+                pg,
+                "INSERT INTO t VALUES ($2, $1, $3, '$4 stays put', $4)",
+                vec![
+                    ParameterValue::Int16(7),
+                    ParameterValue::Int64(1),
+                    ParameterValue::Boolean(true),
+                    ParameterValue::Binary(b"bytes".to_vec()),
+                ],
+            )
+            .unwrap()
+    );
+
+    let rows = engine
+        .query(
+            Dialect::Postgres,
+            pg,
+            "SELECT id, small, flag, name, data, small + 1 AS computed FROM t WHERE id = $1",
+            vec![ParameterValue::Int64(1)],
+        )
+        .unwrap();
+
+    assert!(matches!(
+        rows.columns
+            .iter()
+            .map(|column| column.data_type)
+            .collect::<Vec<_>>()
+            .as_slice(),
+        &[
+            DbDataType::Int64,
+            DbDataType::Int16,
+            DbDataType::Boolean,
+            DbDataType::Str,
+            DbDataType::Binary,
+            DbDataType::Int64
+        ]
+    ));
+    assert_eq!("computed", rows.columns[5].name);
+
+    assert!(matches!(
+        rows.rows[0].as_slice(),
+        [
+            DbValue::Int64(1),
+            DbValue::Int16(7),
+            DbValue::Boolean(true),
+            DbValue::Str(name),
+            DbValue::Binary(data),
+            DbValue::Int64(8)
+        ] if name == "$4 stays put" && data == b"bytes"
+    ));
+
+    // Databases are per-address, and each interface only accepts its own scheme:
+    assert!(matches!(
+        engine.query(
+            Dialect::Mysql,
+            "mysql://localhost/test",
+            "SELECT * FROM t",
+            Vec::new()
+        ),
+        Err(SqlError::QueryFailed(_))
+    ));
+    assert!(matches!(
+        engine.query(Dialect::Mysql, pg, "SELECT * FROM t", Vec::new()),
+        Err(SqlError::ConnectionFailed(_))
+    ));
+    assert!(matches!(
+        engine.execute(
+            Dialect::Postgres,
+            pg,
+            "INSERT INTO t (id) VALUES ($1)",
+            vec![ParameterValue::Uint64(u64::MAX)]
+        ),
+        Err(SqlError::BadParameter(_))
+    ));
+
+    engine
+        .execute(
+            Dialect::Postgres,
+            pg,
+            "INSERT INTO t (id, small) VALUES (2, 100000)",
+            Vec::new(),
+        )
+        .unwrap();
+    assert!(matches!(
+        engine.query(
+            Dialect::Postgres,
+            pg,
+            "SELECT small FROM t WHERE id = 2",
+            Vec::new()
+        ),
+        Err(SqlError::ValueConversionFailed(_))
+    ));
+}
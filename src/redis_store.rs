@@ -0,0 +1,529 @@
+//! In-process stand-in for a Redis server, backing the `redis` interface
+//!
+//! Each distinct `address` passed by the guest gets its own keyspace, so guests which talk to several "servers"
+//! see the same isolation they would in production.
+
+use {
+    super::{redis, redis_types, resp::RespClient, Host, RedisParameter, RedisResult},
+    anyhow::{anyhow, bail, Result},
+    async_trait::async_trait,
+    std::{
+        collections::{HashMap, HashSet},
+        sync::{
+            mpsc::{self, Receiver, Sender},
+            Arc, Mutex,
+        },
+    },
+};
+
+const WRONG_TYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+const NOT_INTEGER: &str = "ERR value is not an integer or out of range";
+
+/// A Redis reply, as returned by a server in response to a command
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    Nil,
+    Status(String),
+    Int(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+enum Value {
+    String(Vec<u8>),
+    Set(HashSet<Vec<u8>>),
+}
+
+#[derive(Default)]
+struct Keyspace {
+    values: HashMap<String, Value>,
+    subscribers: HashMap<Vec<u8>, Vec<Sender<Vec<u8>>>>,
+}
+
+/// A set of in-memory keyspaces, indexed by address
+#[derive(Default)]
+pub struct RedisStore {
+    keyspaces: Mutex<HashMap<String, Keyspace>>,
+}
+
+impl RedisStore {
+    /// Subscribe to messages published to `channel` on the server at `address`
+    pub fn subscribe(&self, address: &str, channel: &str) -> Receiver<Vec<u8>> {
+        let (tx, rx) = mpsc::channel();
+        self.keyspaces
+            .lock()
+            .unwrap()
+            .entry(address.to_owned())
+            .or_default()
+            .subscribers
+            .entry(channel.as_bytes().to_vec())
+            .or_default()
+            .push(tx);
+        rx
+    }
+
+    /// Execute the specified command (i.e. the command name followed by its arguments) against the keyspace for
+    /// `address`
+    pub fn execute(&self, address: &str, command: &[Vec<u8>]) -> Result<Reply> {
+        let (name, args) = command
+            .split_first()
+            .ok_or_else(|| anyhow!("ERR empty command"))?;
+        let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+
+        let arity = |min: usize| {
+            if args.len() < min {
+                Err(anyhow!(
+                    "ERR wrong number of arguments for '{}' command",
+                    name.to_ascii_lowercase()
+                ))
+            } else {
+                Ok(())
+            }
+        };
+
+        let mut keyspaces = self.keyspaces.lock().unwrap();
+        let keyspace = keyspaces.entry(address.to_owned()).or_default();
+
+        Ok(match name.as_str() {
+            "PING" => Reply::Status("PONG".to_owned()),
+
+            "GET" => {
+                arity(1)?;
+                match keyspace.values.get(&key(&args[0])?) {
+                    None => Reply::Nil,
+                    Some(Value::String(value)) => Reply::Bulk(value.clone()),
+                    Some(Value::Set(_)) => bail!(WRONG_TYPE),
+                }
+            }
+
+            "SET" => {
+                arity(2)?;
+                keyspace
+                    .values
+                    .insert(key(&args[0])?, Value::String(args[1].clone()));
+                Reply::Status("OK".to_owned())
+            }
+
+            "INCR" | "INCRBY" | "DECR" | "DECRBY" => {
+                let delta = match name.as_str() {
+                    "INCR" => 1,
+                    "DECR" => -1,
+                    _ => {
+                        arity(2)?;
+                        let delta = integer(&args[1])?;
+                        if name == "DECRBY" {
+                            delta.checked_neg().ok_or_else(|| anyhow!(NOT_INTEGER))?
+                        } else {
+                            delta
+                        }
+                    }
+                };
+                arity(1)?;
+
+                let value = keyspace
+                    .values
+                    .entry(key(&args[0])?)
+                    .or_insert_with(|| Value::String(b"0".to_vec()));
+
+                let Value::String(bytes) = value else {
+                    bail!(WRONG_TYPE)
+                };
+
+                let result = integer(bytes.as_slice())?
+                    .checked_add(delta)
+                    .ok_or_else(|| anyhow!("ERR increment or decrement would overflow"))?;
+
+                *bytes = result.to_string().into_bytes();
+                Reply::Int(result)
+            }
+
+            "DEL" => {
+                arity(1)?;
+                let mut count = 0;
+                for arg in args {
+                    if keyspace.values.remove(&key(arg)?).is_some() {
+                        count += 1;
+                    }
+                }
+                Reply::Int(count)
+            }
+
+            "EXISTS" => {
+                arity(1)?;
+                let mut count = 0;
+                for arg in args {
+                    if keyspace.values.contains_key(&key(arg)?) {
+                        count += 1;
+                    }
+                }
+                Reply::Int(count)
+            }
+
+            "SADD" => {
+                arity(2)?;
+                let value = keyspace
+                    .values
+                    .entry(key(&args[0])?)
+                    .or_insert_with(|| Value::Set(HashSet::new()));
+
+                let Value::Set(set) = value else {
+                    bail!(WRONG_TYPE)
+                };
+
+                Reply::Int(
+                    args[1..]
+                        .iter()
+                        .filter(|member| set.insert(member.to_vec()))
+                        .count() as i64,
+                )
+            }
+
+            "SMEMBERS" => {
+                arity(1)?;
+                match keyspace.values.get(&key(&args[0])?) {
+                    None => Reply::Array(Vec::new()),
+                    Some(Value::Set(set)) => Reply::Array(
+                        set.iter()
+                            .map(|member| Reply::Bulk(member.clone()))
+                            .collect(),
+                    ),
+                    Some(Value::String(_)) => bail!(WRONG_TYPE),
+                }
+            }
+
+            "SREM" => {
+                arity(2)?;
+                let key = key(&args[0])?;
+                match keyspace.values.get_mut(&key) {
+                    None => Reply::Int(0),
+                    Some(Value::Set(set)) => {
+                        let count = args[1..]
+                            .iter()
+                            .filter(|member| set.remove(member.as_slice()))
+                            .count();
+
+                        if set.is_empty() {
+                            keyspace.values.remove(&key);
+                        }
+
+                        Reply::Int(count as i64)
+                    }
+                    Some(Value::String(_)) => bail!(WRONG_TYPE),
+                }
+            }
+
+            "PUBLISH" => {
+                arity(2)?;
+                let subscribers = keyspace
+                    .subscribers
+                    .get_mut(&args[0])
+                    .map(|subscribers| {
+                        subscribers.retain(|tx| tx.send(args[1].clone()).is_ok());
+                        subscribers.len()
+                    })
+                    .unwrap_or(0);
+                Reply::Int(subscribers as i64)
+            }
+
+            "FLUSHDB" => {
+                keyspace.values.clear();
+                Reply::Status("OK".to_owned())
+            }
+
+            _ => bail!(
+                "ERR unknown command '{}'",
+                String::from_utf8_lossy(&command[0])
+            ),
+        })
+    }
+}
+
+fn key(arg: &[u8]) -> Result<String> {
+    Ok(String::from_utf8(arg.to_vec())?)
+}
+
+fn integer(arg: &[u8]) -> Result<i64> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!(NOT_INTEGER))
+}
+
+fn command(name: &str, key: &str, args: impl IntoIterator<Item = Vec<u8>>) -> Vec<Vec<u8>> {
+    [name.as_bytes().to_vec(), key.as_bytes().to_vec()]
+        .into_iter()
+        .chain(args)
+        .collect()
+}
+
+impl Reply {
+    fn into_int(self) -> Result<i64> {
+        match self {
+            Self::Int(value) => Ok(value),
+            reply => Err(anyhow!("expected integer reply; got {reply:?}")),
+        }
+    }
+
+    fn into_ok(self) -> Result<()> {
+        match self {
+            Self::Status(status) if status == "OK" => Ok(()),
+            reply => Err(anyhow!("expected OK reply; got {reply:?}")),
+        }
+    }
+
+    fn into_results(self) -> Vec<RedisResult> {
+        match self {
+            Self::Nil => vec![RedisResult::Nil],
+            Self::Status(status) => vec![RedisResult::Status(status)],
+            Self::Int(value) => vec![RedisResult::Int64(value)],
+            Self::Bulk(value) => vec![RedisResult::Binary(value)],
+            Self::Array(replies) => replies.into_iter().flat_map(Self::into_results).collect(),
+        }
+    }
+}
+
+/// Where a `Host` sends the guest's Redis commands
+#[derive(Clone)]
+pub enum RedisBackend {
+    /// Execute commands directly against an in-process store
+    Embedded(Arc<RedisStore>),
+
+    /// Send commands over TCP to whatever server the guest addresses
+    Resp(Arc<RespClient>),
+}
+
+impl Default for RedisBackend {
+    fn default() -> Self {
+        Self::Embedded(Arc::default())
+    }
+}
+
+impl RedisBackend {
+    pub async fn execute(&self, address: &str, command: &[Vec<u8>]) -> Result<Reply> {
+        match self {
+            Self::Embedded(store) => store.execute(address, command),
+            Self::Resp(client) => client.execute(address, command).await,
+        }
+    }
+}
+
+impl Host {
+    async fn redis_execute<T>(
+        &self,
+        address: &str,
+        command: &[Vec<u8>],
+        convert: impl FnOnce(Reply) -> Result<T>,
+    ) -> Result<Result<T, redis_types::Error>> {
+        Ok(self
+            .redis
+            .execute(address, command)
+            .await
+            .and_then(convert)
+            .map_err(|_| redis_types::Error::Error))
+    }
+}
+
+#[async_trait]
+impl redis::Host for Host {
+    async fn publish(
+        &mut self,
+        address: String,
+        channel: String,
+        payload: Vec<u8>,
+    ) -> Result<Result<(), redis_types::Error>> {
+        self.redis_execute(
+            &address,
+            &command("PUBLISH", &channel, [payload]),
+            |reply| reply.into_int().map(drop),
+        )
+        .await
+    }
+
+    async fn get(
+        &mut self,
+        address: String,
+        key: String,
+    ) -> Result<Result<Vec<u8>, redis_types::Error>> {
+        self.redis_execute(&address, &command("GET", &key, []), |reply| match reply {
+            Reply::Nil => Ok(Vec::new()),
+            Reply::Bulk(value) => Ok(value),
+            reply => Err(anyhow!("expected bulk reply; got {reply:?}")),
+        })
+        .await
+    }
+
+    async fn set(
+        &mut self,
+        address: String,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<Result<(), redis_types::Error>> {
+        self.redis_execute(&address, &command("SET", &key, [value]), Reply::into_ok)
+            .await
+    }
+
+    async fn incr(
+        &mut self,
+        address: String,
+        key: String,
+    ) -> Result<Result<i64, redis_types::Error>> {
+        self.redis_execute(&address, &command("INCR", &key, []), Reply::into_int)
+            .await
+    }
+
+    async fn del(
+        &mut self,
+        address: String,
+        keys: Vec<String>,
+    ) -> Result<Result<i64, redis_types::Error>> {
+        let command = [b"DEL".to_vec()]
+            .into_iter()
+            .chain(keys.into_iter().map(String::into_bytes))
+            .collect::<Vec<_>>();
+
+        self.redis_execute(&address, &command, Reply::into_int)
+            .await
+    }
+
+    async fn sadd(
+        &mut self,
+        address: String,
+        key: String,
+        values: Vec<String>,
+    ) -> Result<Result<i64, redis_types::Error>> {
+        self.redis_execute(
+            &address,
+            &command("SADD", &key, values.into_iter().map(String::into_bytes)),
+            Reply::into_int,
+        )
+        .await
+    }
+
+    async fn smembers(
+        &mut self,
+        address: String,
+        key: String,
+    ) -> Result<Result<Vec<String>, redis_types::Error>> {
+        self.redis_execute(
+            &address,
+            &command("SMEMBERS", &key, []),
+            |reply| match reply {
+                Reply::Array(replies) => replies
+                    .into_iter()
+                    .map(|reply| -> Result<String> {
+                        match reply {
+                            Reply::Bulk(value) => Ok(String::from_utf8(value)?),
+                            reply => Err(anyhow!("expected bulk reply; got {reply:?}")),
+                        }
+                    })
+                    .collect(),
+                reply => Err(anyhow!("expected array reply; got {reply:?}")),
+            },
+        )
+        .await
+    }
+
+    async fn srem(
+        &mut self,
+        address: String,
+        key: String,
+        values: Vec<String>,
+    ) -> Result<Result<i64, redis_types::Error>> {
+        self.redis_execute(
+            &address,
+            &command("SREM", &key, values.into_iter().map(String::into_bytes)),
+            Reply::into_int,
+        )
+        .await
+    }
+
+    async fn execute(
+        &mut self,
+        address: String,
+        command: String,
+        arguments: Vec<RedisParameter>,
+    ) -> Result<Result<Vec<RedisResult>, redis_types::Error>> {
+        let command = [command.into_bytes()]
+            .into_iter()
+            .chain(arguments.into_iter().map(|argument| match argument {
+                RedisParameter::Int64(value) => value.to_string().into_bytes(),
+                RedisParameter::Binary(value) => value,
+            }))
+            .collect::<Vec<_>>();
+
+        self.redis_execute(&address, &command, |reply| Ok(reply.into_results()))
+            .await
+    }
+}
+
+#[test]
+fn redis_commands() {
+    let redis = RedisStore::default();
+    let run = |address: &str, command: &[&str]| {
+        redis.execute(
+            address,
+            &command
+                .iter()
+                .map(|arg| arg.as_bytes().to_vec())
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    assert_eq!(Reply::Int(1), run("a", &["INCR", "counter"]).unwrap());
+    assert_eq!(Reply::Int(2), run("a", &["incr", "counter"]).unwrap());
+    assert_eq!(
+        Reply::Int(12),
+        run("a", &["INCRBY", "counter", "10"]).unwrap()
+    );
+
+    // Keyspaces are per-address:
+    assert_eq!(Reply::Nil, run("b", &["GET", "counter"]).unwrap());
+    assert_eq!(
+        Reply::Bulk(b"12".to_vec()),
+        run("a", &["GET", "counter"]).unwrap()
+    );
+
+    run("a", &["SET", "text", "hello"]).unwrap();
+    assert_eq!(
+        NOT_INTEGER,
+        run("a", &["INCR", "text"]).unwrap_err().to_string()
+    );
+
+    run("a", &["SET", "max", &i64::MAX.to_string()]).unwrap();
+    assert!(run("a", &["INCR", "max"]).is_err());
+
+    assert_eq!(
+        Reply::Int(2),
+        run("a", &["SADD", "set", "x", "y", "x"]).unwrap()
+    );
+    assert_eq!(
+        WRONG_TYPE,
+        run("a", &["INCR", "set"]).unwrap_err().to_string()
+    );
+    assert_eq!(
+        WRONG_TYPE,
+        run("a", &["GET", "set"]).unwrap_err().to_string()
+    );
+    assert_eq!(
+        WRONG_TYPE,
+        run("a", &["SADD", "text", "x"]).unwrap_err().to_string()
+    );
+    assert_eq!(Reply::Int(1), run("a", &["SREM", "set", "x", "z"]).unwrap());
+    assert_eq!(
+        Reply::Array(vec![Reply::Bulk(b"y".to_vec())]),
+        run("a", &["SMEMBERS", "set"]).unwrap()
+    );
+
+    assert_eq!(
+        Reply::Int(2),
+        run("a", &["DEL", "set", "text", "missing"]).unwrap()
+    );
+
+    let messages = redis.subscribe("a", "news");
+    assert_eq!(Reply::Int(1), run("a", &["PUBLISH", "news", "hi"]).unwrap());
+    assert_eq!(Reply::Int(0), run("b", &["PUBLISH", "news", "hi"]).unwrap());
+    assert_eq!(b"hi" as &[_], &messages.try_recv().unwrap());
+
+    assert!(run("a", &["BOGUS"]).is_err());
+}
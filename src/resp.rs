@@ -0,0 +1,357 @@
+//! Minimal [RESP](https://redis.io/docs/reference/protocol-spec/) client and server
+//!
+//! The client keeps a pool of idle connections per address, which is what a production host pays on each request
+//! once warmed up.  The server simply exposes a `RedisStore` on a loopback port so the client has something to
+//! talk to without an external Redis installation.
+
+use {
+    super::redis_store::{RedisStore, Reply},
+    anyhow::{anyhow, bail, Result},
+    std::{
+        collections::HashMap,
+        future::Future,
+        net::SocketAddr,
+        pin::Pin,
+        sync::{Arc, Mutex},
+    },
+    tokio::{
+        io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    },
+};
+
+type Connection = BufReader<TcpStream>;
+
+/// Longest bulk string we'll accept from a peer, matching Redis' own `proto-max-bulk-len` default
+const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024;
+
+fn encode_command(command: &[Vec<u8>]) -> Vec<u8> {
+    let mut buffer = format!("*{}\r\n", command.len()).into_bytes();
+    for arg in command {
+        buffer.extend(format!("${}\r\n", arg.len()).bytes());
+        buffer.extend(arg);
+        buffer.extend(b"\r\n");
+    }
+    buffer
+}
+
+fn encode_reply(reply: &Reply, buffer: &mut Vec<u8>) {
+    match reply {
+        Reply::Nil => buffer.extend(b"$-1\r\n"),
+        Reply::Status(status) => buffer.extend(format!("+{status}\r\n").bytes()),
+        Reply::Int(value) => buffer.extend(format!(":{value}\r\n").bytes()),
+        Reply::Bulk(value) => {
+            buffer.extend(format!("${}\r\n", value.len()).bytes());
+            buffer.extend(value);
+            buffer.extend(b"\r\n");
+        }
+        Reply::Array(replies) => {
+            buffer.extend(format!("*{}\r\n", replies.len()).bytes());
+            for reply in replies {
+                encode_reply(reply, buffer);
+            }
+        }
+    }
+}
+
+async fn read_line(reader: &mut (impl AsyncBufRead + Unpin + Send)) -> Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+
+    line.strip_suffix("\r\n")
+        .map(|line| Some(line.to_owned()))
+        .ok_or_else(|| anyhow!("RESP line not terminated by CRLF: {line:?}"))
+}
+
+async fn read_bulk(
+    reader: &mut (impl AsyncBufRead + Unpin + Send),
+    length: usize,
+) -> Result<Vec<u8>> {
+    if length > MAX_BULK_LENGTH {
+        bail!("RESP bulk string length {length} exceeds maximum of {MAX_BULK_LENGTH}");
+    }
+    let padded = length
+        .checked_add(2)
+        .ok_or_else(|| anyhow!("RESP bulk string length overflow"))?;
+    let mut value = vec![0; padded];
+    reader.read_exact(&mut value).await?;
+    if !value.ends_with(b"\r\n") {
+        bail!("RESP bulk string not terminated by CRLF");
+    }
+    value.truncate(length);
+    Ok(value)
+}
+
+/// Read a reply, returning `Ok(None)` if the peer closed the connection cleanly, and `Ok(Some(Err(_)))` if the
+/// server replied with an error
+fn read_reply<'a, R: AsyncBufRead + Unpin + Send>(
+    reader: &'a mut R,
+) -> Pin<Box<dyn Future<Output = Result<Option<Result<Reply>>>> + Send + 'a>> {
+    Box::pin(async move {
+        let Some(line) = read_line(reader).await? else {
+            return Ok(None);
+        };
+
+        let mut chars = line.chars();
+        let tag = chars.next();
+        let rest = chars.as_str();
+
+        Ok(Some(match tag {
+            Some('+') => Ok(Reply::Status(rest.to_owned())),
+            Some('-') => Err(anyhow!("{rest}")),
+            Some(':') => Ok(Reply::Int(rest.parse()?)),
+            Some('$') => match rest.parse::<i64>()? {
+                -1 => Ok(Reply::Nil),
+                length => Ok(Reply::Bulk(
+                    read_bulk(reader, usize::try_from(length)?).await?,
+                )),
+            },
+            Some('*') => match rest.parse::<i64>()? {
+                -1 => Ok(Reply::Nil),
+                length => {
+                    let mut replies = Vec::with_capacity(usize::try_from(length)?);
+                    for _ in 0..length {
+                        replies.push(
+                            read_reply(reader)
+                                .await?
+                                .ok_or_else(|| anyhow!("unexpected EOF in RESP array"))??,
+                        );
+                    }
+                    Ok(Reply::Array(replies))
+                }
+            },
+            _ => bail!("unexpected RESP line: {line:?}"),
+        }))
+    })
+}
+
+/// Read a command sent by a client, which must be an array of bulk strings, returning `Ok(None)` on EOF
+async fn read_command(
+    reader: &mut (impl AsyncBufRead + Unpin + Send),
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+
+    let count = line
+        .strip_prefix('*')
+        .ok_or_else(|| anyhow!("expected RESP array; got {line:?}"))?
+        .parse::<usize>()?;
+
+    let mut command = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_line(reader)
+            .await?
+            .ok_or_else(|| anyhow!("unexpected EOF in RESP command"))?;
+
+        let length = line
+            .strip_prefix('$')
+            .ok_or_else(|| anyhow!("expected RESP bulk string; got {line:?}"))?
+            .parse()?;
+
+        command.push(read_bulk(reader, length).await?);
+    }
+
+    Ok(Some(command))
+}
+
+async fn write_all(writer: &mut (impl AsyncWrite + Unpin), buffer: &[u8]) -> Result<()> {
+    writer.write_all(buffer).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// RESP client with a pool of idle connections per address
+#[derive(Default)]
+pub struct RespClient {
+    aliases: HashMap<String, SocketAddr>,
+    pool: Mutex<HashMap<String, Vec<Connection>>>,
+}
+
+impl RespClient {
+    /// Connect to `target` whenever a guest asks for `address`
+    ///
+    /// This lets a guest with a hard-coded address (e.g. "redis://127.0.0.1:6379") be pointed at a server on an
+    /// ephemeral port.  Addresses without an alias are resolved and connected to directly.
+    pub fn alias(mut self, address: &str, target: SocketAddr) -> Self {
+        self.aliases.insert(address.to_owned(), target);
+        self
+    }
+
+    async fn connect(&self, address: &str) -> Result<Connection> {
+        let stream = if let Some(target) = self.aliases.get(address) {
+            TcpStream::connect(target).await?
+        } else {
+            let authority = address.strip_prefix("redis://").unwrap_or(address);
+            let authority = authority.split('/').next().unwrap_or(authority);
+            TcpStream::connect(authority).await?
+        };
+
+        stream.set_nodelay(true)?;
+
+        Ok(BufReader::new(stream))
+    }
+
+    /// Send the specified command to the server at `address` and return its reply
+    pub async fn execute(&self, address: &str, command: &[Vec<u8>]) -> Result<Reply> {
+        let idle = self
+            .pool
+            .lock()
+            .unwrap()
+            .get_mut(address)
+            .and_then(Vec::pop);
+
+        let mut connection = if let Some(connection) = idle {
+            connection
+        } else {
+            self.connect(address).await?
+        };
+
+        write_all(connection.get_mut(), &encode_command(command)).await?;
+
+        let reply = read_reply(&mut connection)
+            .await?
+            .ok_or_else(|| anyhow!("connection to {address} closed unexpectedly"))?;
+
+        // Only connections which completed a full round trip are returned to the pool; anything else may be left
+        // in an indeterminate state.
+        self.pool
+            .lock()
+            .unwrap()
+            .entry(address.to_owned())
+            .or_default()
+            .push(connection);
+
+        reply
+    }
+}
+
+/// RESP server exposing a `RedisStore` on a loopback port
+///
+/// The server runs as a task on the current Tokio runtime and is stopped when this handle is dropped.
+pub struct RespServer {
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl RespServer {
+    /// Start serving `store` on an ephemeral loopback port
+    ///
+    /// All clients share a single keyspace, just as they would with a real server.
+    pub async fn start(store: Arc<RedisStore>) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    break;
+                };
+
+                let store = store.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(store, address, stream).await {
+                        eprintln!("RESP connection error: {e:?}");
+                    }
+                });
+            }
+        });
+
+        Ok(Self { address, task })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for RespServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(store: Arc<RedisStore>, address: SocketAddr, stream: TcpStream) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let keyspace = address.to_string();
+    let mut stream = BufReader::new(stream);
+    let mut buffer = Vec::new();
+
+    while let Some(command) = read_command(&mut stream).await? {
+        buffer.clear();
+        match store.execute(&keyspace, &command) {
+            Ok(reply) => encode_reply(&reply, &mut buffer),
+            Err(e) => buffer.extend(format!("-{e}\r\n").bytes()),
+        }
+        write_all(stream.get_mut(), &buffer).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resp_round_trip() -> Result<()> {
+    let server = RespServer::start(Arc::default()).await?;
+    let client = RespClient::default().alias("redis://example", server.address());
+
+    let run = |command: &[&str]| {
+        let command = command
+            .iter()
+            .map(|arg| arg.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let client = &client;
+        async move { client.execute("redis://example", &command).await }
+    };
+
+    assert_eq!(
+        Reply::Status("OK".to_owned()),
+        run(&["SET", "foo", "bar"]).await?
+    );
+    assert_eq!(Reply::Bulk(b"bar".to_vec()), run(&["GET", "foo"]).await?);
+    assert_eq!(Reply::Nil, run(&["GET", "missing"]).await?);
+    assert_eq!(Reply::Int(2), run(&["SADD", "set", "a", "b"]).await?);
+    assert_eq!(Reply::Int(1), run(&["SREM", "set", "a"]).await?);
+    assert_eq!(
+        Reply::Array(vec![Reply::Bulk(b"b".to_vec())]),
+        run(&["SMEMBERS", "set"]).await?
+    );
+
+    let error = run(&["INCR", "foo"]).await.unwrap_err().to_string();
+    assert!(error.starts_with("ERR"), "{error}");
+
+    let error = run(&["SMEMBERS", "foo"]).await.unwrap_err().to_string();
+    assert!(error.starts_with("WRONGTYPE"), "{error}");
+
+    // The error reply above should not have poisoned the pooled connection:
+    assert_eq!(Reply::Int(1), run(&["INCR", "counter"]).await?);
+    assert_eq!(
+        1,
+        client
+            .pool
+            .lock()
+            .unwrap()
+            .get("redis://example")
+            .unwrap()
+            .len()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resp_bulk_length_limit() {
+    let error = read_bulk(&mut &b"\r\n"[..], MAX_BULK_LENGTH + 1)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("exceeds maximum"), "{error}");
+
+    let mut reader = &b"$9999999999\r\n"[..];
+    assert!(read_reply(&mut reader).await.is_err());
+    assert!(read_command(&mut &b"*1\r\n$18446744073709551615\r\n"[..])
+        .await
+        .is_err());
+}
@@ -0,0 +1,222 @@
+//! Spin-style HTTP routing: matches request paths against component routes, honoring the application's base path
+//!
+//! A route is either exact (e.g. "/foo") or a wildcard (e.g. "/foo/..." or "/..."), which matches its prefix and
+//! anything beneath it.  An exact match always wins; otherwise the wildcard with the longest prefix wins.
+
+use anyhow::{bail, Result};
+
+enum Pattern {
+    Exact(String),
+    /// The prefix preceding "/...", e.g. "/foo" for "/foo/..." or "" for "/..."
+    Wildcard(String),
+}
+
+impl Pattern {
+    fn parse(base: &str, route: &str) -> Self {
+        if let Some(prefix) = route.strip_suffix("/...") {
+            Self::Wildcard(join(base, prefix))
+        } else {
+            Self::Exact(or_root(join(base, route)))
+        }
+    }
+
+    /// If `path` matches this pattern, return the length of the matched prefix
+    fn matches(&self, path: &str) -> Option<usize> {
+        match self {
+            Self::Exact(route) => (path == route).then_some(route.len()),
+            Self::Wildcard(prefix) => (path == prefix
+                || path
+                    .strip_prefix(prefix.as_str())
+                    .map_or(false, |rest| rest.starts_with('/')))
+            .then_some(prefix.len()),
+        }
+    }
+}
+
+/// Join a base path and a route, e.g. "/api" and "/foo" to "/api/foo", without doubling or trailing slashes
+fn join(base: &str, route: &str) -> String {
+    let base = base.trim_end_matches('/');
+    let route = route.trim_end_matches('/');
+    if route.is_empty() || route.starts_with('/') {
+        format!("{base}{route}")
+    } else {
+        format!("{base}/{route}")
+    }
+}
+
+fn or_root(path: String) -> String {
+    if path.is_empty() {
+        "/".to_owned()
+    } else {
+        path
+    }
+}
+
+/// The result of routing a request, as exposed to the guest via `params`
+pub struct RouteMatch {
+    /// The part of the path following the matched route, e.g. "/bar" for "/foo/bar" matched by "/foo/..."
+    pub path_info: String,
+    /// The matched route, including the base path, e.g. "/api/foo/..."
+    pub matched_route: String,
+    pub base_path: String,
+    /// The component's route without any wildcard suffix, e.g. "/foo"
+    pub component_route: String,
+    /// The component's route as written in the manifest, e.g. "/foo/..."
+    pub raw_component_route: String,
+}
+
+impl RouteMatch {
+    /// Return the `params` to pass to the guest, named after the equivalent headers in Spin
+    pub fn params(&self) -> [(&str, &str); 5] {
+        [
+            ("spin-path-info", self.path_info.as_str()),
+            ("spin-matched-route", self.matched_route.as_str()),
+            ("spin-base-path", self.base_path.as_str()),
+            ("spin-component-route", self.component_route.as_str()),
+            (
+                "spin-raw-component-route",
+                self.raw_component_route.as_str(),
+            ),
+        ]
+    }
+}
+
+struct Route<T> {
+    pattern: Pattern,
+    raw: String,
+    target: T,
+}
+
+/// Maps request paths to targets (e.g. components) according to their routes
+pub struct Router<T> {
+    base: String,
+    routes: Vec<Route<T>>,
+}
+
+impl<T> Router<T> {
+    /// Create a router from the application's base path (e.g. "/") and a set of `(route, target)` pairs
+    pub fn new<'a>(base: &str, routes: impl IntoIterator<Item = (&'a str, T)>) -> Result<Self> {
+        let base = join("", base);
+        let mut result = Vec::<Route<T>>::new();
+
+        for (raw, target) in routes {
+            if !raw.starts_with('/') {
+                bail!("route {raw:?} must start with '/'");
+            }
+
+            let pattern = Pattern::parse(&base, raw);
+
+            if result.iter().any(|route| match (&route.pattern, &pattern) {
+                (Pattern::Exact(a), Pattern::Exact(b))
+                | (Pattern::Wildcard(a), Pattern::Wildcard(b)) => a == b,
+                _ => false,
+            }) {
+                bail!("duplicate route: {raw:?}");
+            }
+
+            result.push(Route {
+                pattern,
+                raw: raw.to_owned(),
+                target,
+            });
+        }
+
+        Ok(Self {
+            base,
+            routes: result,
+        })
+    }
+
+    /// Find the target for the specified path (which must not include a query string), if any
+    pub fn route(&self, path: &str) -> Option<(&T, RouteMatch)> {
+        let exact = self
+            .routes
+            .iter()
+            .find(|route| {
+                matches!(route.pattern, Pattern::Exact(_)) && route.pattern.matches(path).is_some()
+            })
+            .map(|route| (route, path.len()));
+
+        let (route, length) = exact.or_else(|| {
+            self.routes
+                .iter()
+                .filter_map(|route| Some((route, route.pattern.matches(path)?)))
+                .max_by_key(|(_, length)| *length)
+        })?;
+
+        let component_route = route.raw.strip_suffix("/...").unwrap_or(&route.raw);
+
+        Some((
+            &route.target,
+            RouteMatch {
+                path_info: path[length..].to_owned(),
+                matched_route: or_root(join(&self.base, &route.raw)),
+                base_path: or_root(self.base.clone()),
+                component_route: component_route.to_owned(),
+                raw_component_route: route.raw.clone(),
+            },
+        ))
+    }
+}
+
+#[test]
+fn routing() -> Result<()> {
+    let router = Router::new(
+        "/",
+        [
+            ("/foo", "foo"),
+            ("/foo/...", "foo-wildcard"),
+            ("/foo/bar/...", "bar-wildcard"),
+            ("/...", "fallback"),
+        ],
+    )?;
+
+    let target = |path| router.route(path).map(|(target, _)| *target);
+
+    assert_eq!(Some("foo"), target("/foo"));
+    assert_eq!(Some("foo-wildcard"), target("/foo/baz"));
+    assert_eq!(Some("bar-wildcard"), target("/foo/bar"));
+    assert_eq!(Some("bar-wildcard"), target("/foo/bar/baz"));
+    assert_eq!(Some("foo-wildcard"), target("/foo/barbell"));
+    assert_eq!(Some("fallback"), target("/food"));
+    assert_eq!(Some("fallback"), target("/"));
+
+    let (_, route) = router.route("/foo/bar/baz/qux").unwrap();
+    assert_eq!(
+        [
+            ("spin-path-info", "/baz/qux"),
+            ("spin-matched-route", "/foo/bar/..."),
+            ("spin-base-path", "/"),
+            ("spin-component-route", "/foo/bar"),
+            ("spin-raw-component-route", "/foo/bar/..."),
+        ],
+        route.params()
+    );
+
+    let (_, route) = router.route("/foo").unwrap();
+    assert_eq!("", route.path_info);
+
+    let router = Router::new("/", [("/", "root"), ("/...", "fallback")])?;
+    assert_eq!(Some(&"root"), router.route("/").map(|(target, _)| target));
+    assert_eq!(
+        Some(&"fallback"),
+        router.route("/foo").map(|(target, _)| target)
+    );
+
+    let router = Router::new("/api/", [("/foo", 1), ("/bar/...", 2)])?;
+    assert_eq!(Some(&1), router.route("/api/foo").map(|(target, _)| target));
+    assert!(router.route("/foo").is_none());
+    assert!(router.route("/api/baz").is_none());
+
+    let (target, route) = router.route("/api/bar/baz").unwrap();
+    assert_eq!(2, *target);
+    assert_eq!("/baz", route.path_info);
+    assert_eq!("/api/bar/...", route.matched_route);
+    assert_eq!("/api", route.base_path);
+
+    assert!(Router::new("/", [("/foo", 1), ("/foo", 2)]).is_err());
+    assert!(Router::new("/", [("/foo/...", 1), ("/foo/...", 2)]).is_err());
+    assert!(Router::new("/", [("foo", 1)]).is_err());
+
+    Ok(())
+}
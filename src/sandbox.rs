@@ -0,0 +1,253 @@
+//! Container-like sandboxing for native code: each call runs in a child process in new user, PID, network, and
+//! mount namespaces, which installs a seccomp-bpf filter before running anything untrusted
+//!
+//! Everything here works unprivileged, provided the kernel allows unprivileged user namespaces.  The filter only
+//! allows the handful of syscalls a request handler needs to allocate memory, write output, and exit; anything else
+//! kills the process.  Since the filter also checks the syscall ABI, only x86_64 and aarch64 are supported.
+
+use {
+    anyhow::{bail, Result},
+    std::{
+        error::Error,
+        fmt::{self, Display},
+        fs,
+        panic::{self, AssertUnwindSafe},
+    },
+};
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+// Classic BPF opcodes (see linux/bpf_common.h), i.e. `BPF_LD | BPF_W | BPF_ABS`, `BPF_JMP | BPF_JEQ | BPF_K`, and
+// `BPF_RET | BPF_K`
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_RET_K: u16 = 0x06;
+
+// Offsets of fields in `struct seccomp_data`
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+/// Syscalls the sandboxed code may make
+const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_futex,
+    libc::SYS_getrandom,
+    libc::SYS_clock_gettime,
+    libc::SYS_sched_yield,
+    libc::SYS_sigaltstack,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+const NAMESPACES: libc::c_int =
+    libc::CLONE_NEWUSER | libc::CLONE_NEWPID | libc::CLONE_NEWNET | libc::CLONE_NEWNS;
+
+fn statement(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// Build a filter which kills the process unless it's running on the expected architecture and the syscall is in
+/// `ALLOWED_SYSCALLS`
+fn filter() -> Vec<libc::sock_filter> {
+    let mut filter = vec![
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        jump(BPF_JMP_JEQ_K, AUDIT_ARCH, 1, 0),
+        statement(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+    ];
+
+    for &syscall in ALLOWED_SYSCALLS {
+        filter.push(jump(BPF_JMP_JEQ_K, syscall as u32, 0, 1));
+        filter.push(statement(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+    }
+
+    filter.push(statement(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS));
+
+    filter
+}
+
+/// The kernel refused to create the namespaces, because unprivileged user namespaces are disabled (`EPERM`) or the
+/// limit on their number has been reached (`ENOSPC`, or `EUSERS` on older kernels)
+#[derive(Debug)]
+pub struct Unavailable(errno::Errno);
+
+impl Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unable to create namespaces (are unprivileged user namespaces enabled?); errno: {}",
+            self.0
+        )
+    }
+}
+
+impl Error for Unavailable {}
+
+/// Runs closures in sandboxed child processes
+pub struct Sandbox {
+    filter: Vec<libc::sock_filter>,
+    uid_map: String,
+    gid_map: String,
+}
+
+impl Sandbox {
+    /// Create a sandbox, checking that it works (e.g. that unprivileged user namespaces are enabled) by running an
+    /// empty closure in it
+    pub fn new() -> Result<Self> {
+        // Map root in the new user namespace to our user outside it, as a container runtime would
+        let sandbox = Self {
+            filter: filter(),
+            uid_map: format!("0 {} 1", unsafe { libc::getuid() }),
+            gid_map: format!("0 {} 1", unsafe { libc::getgid() }),
+        };
+
+        sandbox.run(|| ())?;
+
+        Ok(sandbox)
+    }
+
+    /// Finish setting up the sandbox from within the child, which is already in its new namespaces
+    ///
+    /// Nothing here may allocate, since another of the parent's threads may have held the allocator's lock when it
+    /// cloned the child, so errors are static descriptions.
+    fn enter(&self) -> Result<(), &'static str> {
+        fs::write("/proc/self/setgroups", "deny").map_err(|_| "unable to write setgroups")?;
+        fs::write("/proc/self/uid_map", &self.uid_map).map_err(|_| "unable to write uid_map")?;
+        fs::write("/proc/self/gid_map", &self.gid_map).map_err(|_| "unable to write gid_map")?;
+
+        if -1 == unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } {
+            return Err("prctl(PR_SET_NO_NEW_PRIVS) failed");
+        }
+
+        let program = libc::sock_fprog {
+            len: self
+                .filter
+                .len()
+                .try_into()
+                .map_err(|_| "seccomp filter too long")?,
+            filter: self.filter.as_ptr() as *mut _,
+        };
+
+        if -1
+            == unsafe {
+                libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                )
+            }
+        {
+            return Err("prctl(PR_SET_SECCOMP) failed");
+        }
+
+        Ok(())
+    }
+
+    /// Run `fun` in a new sandboxed child process and wait for it to exit
+    pub fn run(&self, fun: impl FnOnce()) -> Result<()> {
+        // A raw `clone` with no stack behaves like `fork`, except that the child starts in the new namespaces and
+        // is PID 1 in its PID namespace
+        match unsafe { libc::syscall(libc::SYS_clone, NAMESPACES | libc::SIGCHLD, 0, 0, 0, 0) } {
+            -1 => {
+                let errno = errno::errno();
+                if let libc::EPERM | libc::ENOSPC | libc::EUSERS = errno.0 {
+                    return Err(Unavailable(errno).into());
+                }
+                bail!("clone failed; errno: {errno}")
+            }
+            0 => {
+                // I'm the child
+                if let Err(message) = self.enter() {
+                    // Written directly rather than using `eprintln!`, which may allocate
+                    for part in ["unable to enter sandbox: ", message, "\n"] {
+                        unsafe { libc::write(2, part.as_ptr().cast(), part.len()) };
+                    }
+                    unsafe { libc::_exit(2) }
+                }
+
+                // A panic must not unwind past this point, or the child would carry on running the benchmark
+                let code = if panic::catch_unwind(AssertUnwindSafe(fun)).is_ok() {
+                    0
+                } else {
+                    1
+                };
+
+                // Exit without running any destructors for maximum performance
+                unsafe { libc::_exit(code) }
+            }
+            child => {
+                // I'm the parent
+                let child = child as libc::pid_t;
+                let mut status = 0;
+                if -1 == unsafe { libc::waitpid(child, &mut status, 0) } {
+                    bail!("waitpid failed; errno: {}", errno::errno());
+                }
+
+                if libc::WIFSIGNALED(status) {
+                    bail!(
+                        "sandboxed child killed by signal {}",
+                        libc::WTERMSIG(status)
+                    );
+                } else if !(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0) {
+                    bail!(
+                        "sandboxed child exited with status {}",
+                        libc::WEXITSTATUS(status)
+                    );
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn sandbox_filter() -> Result<()> {
+    let filter = filter();
+    assert_eq!(5 + 2 * ALLOWED_SYSCALLS.len(), filter.len());
+    assert_eq!(SECCOMP_DATA_ARCH, filter[0].k);
+    assert_eq!(libc::SECCOMP_RET_KILL_PROCESS, filter.last().unwrap().k);
+
+    // Not every environment allows unprivileged user namespaces (e.g. many CI containers), in which case there's
+    // nothing more we can check
+    let sandbox = match Sandbox::new() {
+        Ok(sandbox) => sandbox,
+        Err(e) if e.is::<Unavailable>() => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    sandbox.run(|| {
+        std::hint::black_box(vec![0_u8; 1 << 20]);
+    })?;
+    assert!(sandbox.run(|| panic!("oops")).is_err());
+
+    // `getppid` isn't in the allowlist, so this should be killed by the filter
+    assert!(sandbox
+        .run(|| unsafe {
+            libc::getppid();
+        })
+        .is_err());
+
+    Ok(())
+}
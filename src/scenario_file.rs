@@ -0,0 +1,438 @@
+//! Declarative scenarios: TOML or JSON files specifying a guest, an instantiation strategy, engine configuration, a
+//! request, and the expected response, so new workloads can be added without editing Rust
+//!
+//! For example:
+//!
+//! ```toml
+//! name = "spin_rust_declarative_pre_instance"
+//! strategy = "pre_instance"
+//!
+//! [guest]
+//! built = "wasm32-wasi/release/spin_guest.wasm"
+//!
+//! [config]
+//! opt_level = "speed"
+//!
+//! [request]
+//! method = "POST"
+//! uri = "/foo?a=b"
+//! headers = { what = "up" }
+//! body = "hello, world!"
+//!
+//! [response]
+//! status = 200
+//! headers = { content-type = "text/plain" }
+//! body = "hola, mundo!"
+//! ```
+
+use {
+    super::{
+        bencher::Bencher,
+        compile_guests,
+        front_end::{Dispatcher, IncomingRequest, Strategy},
+        stdio_host, Method, Response,
+    },
+    anyhow::{anyhow, bail, ensure, Context, Result},
+    serde::Deserialize,
+    std::{
+        collections::BTreeMap,
+        fs,
+        path::{Path, PathBuf},
+    },
+    tokio::runtime::Runtime,
+    wasmtime::{Config, InstanceAllocationStrategy, OptLevel, PoolingAllocationConfig},
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioFile {
+    pub name: String,
+    pub guest: Guest,
+    #[serde(default)]
+    pub strategy: Strategy,
+    #[serde(default)]
+    pub config: EngineConfig,
+    pub request: RequestSpec,
+    #[serde(default)]
+    pub response: ResponseSpec,
+    /// Directory containing the file, which `guest.path` is relative to
+    #[serde(skip)]
+    root: PathBuf,
+}
+
+/// The guest to run; exactly one of `built` and `path` must be specified
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Guest {
+    /// A guest built by this crate, relative to the build output directory, e.g.
+    /// `wasm32-wasi/release/spin_guest.wasm`
+    #[serde(default)]
+    pub built: Option<String>,
+    /// Any other guest, relative to the scenario file
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Whether the guest is a core module which must be componentized (rather than already a component)
+    #[serde(default = "default_componentize")]
+    pub componentize: bool,
+}
+
+fn default_componentize() -> bool {
+    true
+}
+
+/// `Config` knobs; anything unspecified keeps Wasmtime's default
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfig {
+    /// One of "none", "speed", or "speed_and_size"
+    pub opt_level: Option<String>,
+    pub parallel_compilation: Option<bool>,
+    pub static_memory_maximum_size: Option<u64>,
+    pub static_memory_guard_size: Option<u64>,
+    pub dynamic_memory_guard_size: Option<u64>,
+    pub guard_before_linear_memory: Option<bool>,
+    pub memory_init_cow: Option<bool>,
+    /// Only used with the pooling strategy
+    pub pooling_instance_count: Option<u32>,
+    /// Only used with the pooling strategy
+    pub pooling_instance_memory_pages: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestSpec {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub uri: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_owned()
+}
+
+/// What the guest must respond with; headers not listed here are ignored, as is the body if unspecified
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseSpec {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl Default for ResponseSpec {
+    fn default() -> Self {
+        Self {
+            status: default_status(),
+            headers: BTreeMap::new(),
+            body: None,
+        }
+    }
+}
+
+fn parse_method(method: &str) -> Result<Method> {
+    Ok(match method.to_ascii_uppercase().as_str() {
+        "GET" => Method::Get,
+        "POST" => Method::Post,
+        "PUT" => Method::Put,
+        "DELETE" => Method::Delete,
+        "PATCH" => Method::Patch,
+        "HEAD" => Method::Head,
+        "OPTIONS" => Method::Options,
+        _ => bail!("unsupported method: {method:?}"),
+    })
+}
+
+impl EngineConfig {
+    fn build(&self, strategy: Strategy) -> Result<Config> {
+        let mut config = Config::new();
+
+        if let Some(opt_level) = &self.opt_level {
+            config.cranelift_opt_level(match opt_level.as_str() {
+                "none" => OptLevel::None,
+                "speed" => OptLevel::Speed,
+                "speed_and_size" => OptLevel::SpeedAndSize,
+                _ => bail!("unsupported opt_level: {opt_level:?}"),
+            });
+        }
+        if let Some(enable) = self.parallel_compilation {
+            config.parallel_compilation(enable);
+        }
+        if let Some(size) = self.static_memory_maximum_size {
+            config.static_memory_maximum_size(size);
+        }
+        if let Some(size) = self.static_memory_guard_size {
+            config.static_memory_guard_size(size);
+        }
+        if let Some(size) = self.dynamic_memory_guard_size {
+            config.dynamic_memory_guard_size(size);
+        }
+        if let Some(enable) = self.guard_before_linear_memory {
+            config.guard_before_linear_memory(enable);
+        }
+        if let Some(enable) = self.memory_init_cow {
+            config.memory_init_cow(enable);
+        }
+
+        if let Strategy::Pooling = strategy {
+            let mut pooling = PoolingAllocationConfig::default();
+            if let Some(count) = self.pooling_instance_count {
+                pooling.instance_count(count);
+            }
+            if let Some(pages) = self.pooling_instance_memory_pages {
+                pooling.instance_memory_pages(pages);
+            }
+            config.allocation_strategy(InstanceAllocationStrategy::Pooling(pooling));
+        } else {
+            ensure!(
+                self.pooling_instance_count.is_none()
+                    && self.pooling_instance_memory_pages.is_none(),
+                "pooling options require the pooling strategy"
+            );
+        }
+
+        Ok(config)
+    }
+}
+
+impl RequestSpec {
+    fn incoming(&self) -> Result<IncomingRequest> {
+        Ok(IncomingRequest {
+            method: parse_method(&self.method)?,
+            uri: self.uri.clone(),
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            body: self.body.as_ref().map(|body| body.as_bytes().to_vec()),
+        })
+    }
+}
+
+impl ResponseSpec {
+    fn check(&self, response: &Response) -> Result<()> {
+        ensure!(
+            self.status == response.status,
+            "expected status {}, got {}",
+            self.status,
+            response.status
+        );
+
+        let headers = response.headers.as_deref().unwrap_or_default();
+        for (name, value) in &self.headers {
+            let actual = headers
+                .iter()
+                .find(|(actual, _)| actual.eq_ignore_ascii_case(name))
+                .map(|(_, actual)| actual)
+                .ok_or_else(|| anyhow!("expected header {name:?} not found"))?;
+
+            ensure!(
+                value == actual,
+                "expected header {name:?} to be {value:?}, got {actual:?}"
+            );
+        }
+
+        if let Some(body) = &self.body {
+            let actual = response.body.as_deref().unwrap_or_default();
+            ensure!(
+                body.as_bytes() == actual,
+                "expected body {body:?}, got {:?}",
+                String::from_utf8_lossy(actual)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl ScenarioFile {
+    /// Parse a scenario, where `json` indicates JSON rather than TOML, and `root` is the directory `guest.path` is
+    /// relative to
+    pub fn parse(text: &str, json: bool, root: &Path) -> Result<Self> {
+        let mut scenario = if json {
+            serde_json::from_str::<Self>(text)?
+        } else {
+            toml::from_str::<Self>(text)?
+        };
+
+        ensure!(
+            scenario.guest.built.is_some() != scenario.guest.path.is_some(),
+            "exactly one of guest.built and guest.path must be specified"
+        );
+
+        // Catch any bad methods or config now rather than when the scenario is run
+        parse_method(&scenario.request.method)?;
+        scenario.config.build(scenario.strategy)?;
+
+        scenario.root = root.to_owned();
+
+        Ok(scenario)
+    }
+
+    /// Read a scenario from a `.toml` or `.json` file
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = path
+            .extension()
+            .map_or(false, |extension| extension == "json");
+        let root = path.parent().unwrap_or_else(|| Path::new("."));
+
+        Self::parse(&fs::read_to_string(path)?, json, root)
+            .with_context(|| path.display().to_string())
+    }
+
+    /// Read every `.toml` and `.json` file in `dir`, in order of file name
+    pub fn read_dir(dir: &Path) -> Result<Vec<Self>> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        paths.retain(|path| {
+            path.extension().map_or(false, |extension| {
+                extension == "toml" || extension == "json"
+            })
+        });
+        paths.sort();
+
+        paths.iter().map(|path| Self::read(path)).collect()
+    }
+
+    fn component(&self) -> Result<Vec<u8>> {
+        let bytes = match (&self.guest.built, &self.guest.path) {
+            (Some(built), _) => {
+                compile_guests();
+                fs::read(format!("{}/{built}", env!("OUT_DIR")))?
+            }
+            (None, Some(path)) => fs::read(self.root.join(path))?,
+            (None, None) => unreachable!(),
+        };
+
+        if self.guest.componentize {
+            spin_componentize::componentize(&bytes)
+        } else {
+            Ok(bytes)
+        }
+    }
+
+    pub fn run(&self, bencher: &mut Bencher) -> Result<()> {
+        let runtime = Runtime::new()?;
+
+        let dispatcher = runtime.block_on(Dispatcher::from_component(
+            self.component()?,
+            self.strategy,
+            self.config.build(self.strategy)?,
+            stdio_host,
+        ))?;
+
+        let request = self.request.incoming()?;
+
+        // Check the response once up front so that a mismatch is reported as an error rather than a panic
+        self.response
+            .check(&runtime.block_on(dispatcher.dispatch(&request))?)?;
+
+        bencher.iter(|| {
+            let response = runtime.block_on(dispatcher.dispatch(&request)).unwrap();
+            self.response.check(&response).unwrap();
+        });
+
+        Ok(())
+    }
+}
+
+#[test]
+fn scenario_file_parsing() -> Result<()> {
+    let scenarios = ScenarioFile::read_dir(Path::new("scenarios"))?;
+    assert!(scenarios.len() > 1);
+    for scenario in &scenarios {
+        assert!(scenario.guest.built.is_some());
+        assert!(scenario.request.incoming().is_ok());
+    }
+
+    let root = Path::new(".");
+
+    let scenario = ScenarioFile::parse(
+        r#"
+        name = "foo"
+        guest = { path = "foo.wasm", componentize = false }
+        request = { uri = "/foo" }
+        "#,
+        false,
+        root,
+    )?;
+    assert!(matches!(scenario.strategy, Strategy::PreInstance));
+    assert!(!scenario.guest.componentize);
+    assert!(matches!(scenario.request.incoming()?.method, Method::Get));
+
+    let json = ScenarioFile::parse(
+        r#"{
+            "name": "foo",
+            "guest": { "built": "foo.wasm" },
+            "strategy": "pooling",
+            "config": { "pooling_instance_count": 10 },
+            "request": { "method": "put", "uri": "/foo", "body": "bar" }
+        }"#,
+        true,
+        root,
+    )?;
+    assert!(matches!(json.strategy, Strategy::Pooling));
+    assert_eq!(
+        Some(b"bar" as &[_]),
+        json.request.incoming()?.body.as_deref()
+    );
+
+    let invalid = |toml: &str| ScenarioFile::parse(toml, false, root).is_err();
+    let request = "request = { uri = \"/\" }";
+    assert!(invalid(&format!("name = \"foo\"\nguest = {{}}\n{request}")));
+    assert!(invalid(&format!(
+        "name = \"foo\"\nguest = {{ built = \"a\", path = \"b\" }}\n{request}"
+    )));
+    assert!(invalid(&format!(
+        "name = \"foo\"\nguest = {{ built = \"a\" }}\nstrategy = \"forked\"\n{request}"
+    )));
+    assert!(invalid(&format!(
+        "name = \"foo\"\nguest = {{ built = \"a\" }}\nconfig = {{ pooling_instance_count = 1 }}\n{request}"
+    )));
+    assert!(invalid(&format!(
+        "name = \"foo\"\nguest = {{ built = \"a\" }}\nconfig = {{ opt_level = \"fast\" }}\n{request}"
+    )));
+    assert!(invalid(
+        "name = \"foo\"\nguest = { built = \"a\" }\nrequest = { method = \"TRACE\", uri = \"/\" }"
+    ));
+    assert!(invalid(&format!(
+        "name = \"foo\"\nguest = {{ built = \"a\" }}\nunknown = 1\n{request}"
+    )));
+
+    let expected = ResponseSpec {
+        status: 200,
+        headers: [("content-type".to_owned(), "text/plain".to_owned())].into(),
+        body: Some("hola, mundo!".to_owned()),
+    };
+    let response = |status, content_type: &str, body: &[u8]| Response {
+        status,
+        headers: Some(vec![("Content-Type".to_owned(), content_type.to_owned())]),
+        body: Some(body.to_vec()),
+    };
+    assert!(expected
+        .check(&response(200, "text/plain", b"hola, mundo!"))
+        .is_ok());
+    assert!(expected
+        .check(&response(404, "text/plain", b"hola, mundo!"))
+        .is_err());
+    assert!(expected
+        .check(&response(200, "text/html", b"hola, mundo!"))
+        .is_err());
+    assert!(expected
+        .check(&response(200, "text/plain", b"hello, world!"))
+        .is_err());
+
+    Ok(())
+}
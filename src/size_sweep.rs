@@ -0,0 +1,252 @@
+//! Request/response size sweeps: latency as a function of body size, header count, and URI length, for both Spin
+//! (where the request is lowered into guest memory via the canonical ABI) and WAGI (where it's passed via
+//! environment variables and stdin/stdout pipes)
+//!
+//! Both guests echo the request's headers and body, so the response grows along with the request.
+
+use {
+    super::{
+        bencher::Bencher,
+        compile_guests,
+        front_end::{Dispatcher, IncomingRequest, Strategy},
+        stdio_host,
+        wagi::{Mount, WagiExecutor},
+        Method, Response,
+    },
+    anyhow::{anyhow, ensure, Result},
+    tokio::runtime::Runtime,
+    wasmtime::Config,
+};
+
+/// The aspect of the request which varies across a sweep
+#[derive(Copy, Clone, Debug)]
+pub enum Dimension {
+    /// Body size in bytes
+    Body,
+
+    /// Number of headers
+    Headers,
+
+    /// URI length in bytes
+    Uri,
+}
+
+/// All dimensions, in the order the runner sweeps them
+pub const DIMENSIONS: &[Dimension] = &[Dimension::Body, Dimension::Headers, Dimension::Uri];
+
+impl Dimension {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Body => "body",
+            Self::Headers => "headers",
+            Self::Uri => "uri",
+        }
+    }
+
+    /// The values to sweep over
+    pub fn values(self) -> &'static [usize] {
+        match self {
+            Self::Body => &[
+                0,
+                1 << 10,
+                16 << 10,
+                64 << 10,
+                256 << 10,
+                1 << 20,
+                4 << 20,
+                10_000_000,
+            ],
+            Self::Headers => &[0, 1, 10, 50, 100, 200],
+            Self::Uri => &[16, 256, 1 << 10, 4 << 10, 16 << 10, 64 << 10],
+        }
+    }
+
+    /// Build a request with the specified body size, header count, or URI length
+    pub fn request(self, value: usize) -> IncomingRequest {
+        match self {
+            Self::Body => IncomingRequest {
+                method: Method::Post,
+                uri: "/echo".to_owned(),
+                headers: Vec::new(),
+                body: Some(vec![b'x'; value]),
+            },
+
+            Self::Headers => IncomingRequest {
+                method: Method::Get,
+                uri: "/echo".to_owned(),
+                headers: (0..value)
+                    .map(|index| (format!("x-header-{index}"), format!("value-{index}")))
+                    .collect(),
+                body: None,
+            },
+
+            Self::Uri => {
+                let mut uri = "/echo/".to_owned();
+                uri.extend((uri.len()..value).map(|_| 'x'));
+
+                IncomingRequest {
+                    method: Method::Get,
+                    uri,
+                    headers: Vec::new(),
+                    body: None,
+                }
+            }
+        }
+    }
+}
+
+/// Check that `response` echoes `request`
+fn check_echo(request: &IncomingRequest, response: &Response) -> Result<()> {
+    ensure!(
+        200 == response.status,
+        "unexpected status: {}",
+        response.status
+    );
+
+    let headers = response.headers.as_deref().unwrap_or_default();
+
+    let uri_length = headers
+        .iter()
+        .find(|(name, _)| name == "uri-length")
+        .map(|(_, value)| value.as_str())
+        .ok_or_else(|| anyhow!("no uri-length header found"))?;
+    ensure!(
+        request.uri.len().to_string() == uri_length,
+        "guest saw a {uri_length}-byte URI, but we sent {} bytes",
+        request.uri.len()
+    );
+
+    let echoed = headers
+        .iter()
+        .filter(|(name, _)| name.starts_with("x-header-"))
+        .count();
+    ensure!(
+        request.headers.len() == echoed,
+        "guest echoed {echoed} headers, but we sent {}",
+        request.headers.len()
+    );
+
+    let body = response.body.as_deref().unwrap_or_default();
+    ensure!(
+        request.body.as_deref().unwrap_or_default() == body,
+        "guest echoed a {}-byte body, but we sent {} bytes",
+        body.len(),
+        request.body.as_ref().map_or(0, Vec::len)
+    );
+
+    Ok(())
+}
+
+/// How the request is passed to the guest
+#[derive(Copy, Clone, Debug)]
+pub enum Interface {
+    /// `inbound-http.handle-request`, using a pre-instantiated component
+    Spin,
+
+    /// CGI-style, using a pre-instantiated module
+    Wagi,
+}
+
+pub struct SweepScenario {
+    pub name: &'static str,
+    pub interface: Interface,
+}
+
+/// All sweep scenarios, in the order the runner executes them
+pub const SWEEP_SCENARIOS: &[SweepScenario] = &[
+    SweepScenario {
+        name: "spin_rust_echo_sweep_pre_instance",
+        interface: Interface::Spin,
+    },
+    SweepScenario {
+        name: "wagi_echo_sweep_pre_instance",
+        interface: Interface::Wagi,
+    },
+];
+
+enum Executor {
+    Spin(Dispatcher),
+    Wagi(WagiExecutor),
+}
+
+impl Executor {
+    async fn execute(&self, request: &IncomingRequest) -> Result<Response> {
+        match self {
+            Self::Spin(dispatcher) => dispatcher.dispatch(request).await,
+            Self::Wagi(executor) => executor.execute(request).await,
+        }
+    }
+}
+
+impl SweepScenario {
+    /// Measure latency with `dimension` set to `value`
+    pub fn run(&self, dimension: Dimension, value: usize, bencher: &mut Bencher) -> Result<()> {
+        compile_guests();
+
+        let runtime = Runtime::new()?;
+
+        let executor = match self.interface {
+            Interface::Spin => Executor::Spin(runtime.block_on(Dispatcher::new(
+                "/wasm32-wasi/release/spin_echo_guest.wasm",
+                Strategy::PreInstance,
+                stdio_host,
+            ))?),
+            Interface::Wagi => Executor::Wagi(WagiExecutor::new(
+                "/wasm32-wasi/release/wagi-echo-guest.wasm",
+                Config::new(),
+                Mount::default(),
+            )?),
+        };
+
+        let request = dimension.request(value);
+
+        // Check the response once up front so that a mismatch is reported as an error rather than a panic
+        check_echo(&request, &runtime.block_on(executor.execute(&request))?)?;
+
+        bencher.iter(|| {
+            let response = runtime.block_on(executor.execute(&request)).unwrap();
+            check_echo(&request, &response).unwrap();
+        });
+
+        Ok(())
+    }
+}
+
+#[test]
+fn sweep_requests() -> Result<()> {
+    for &dimension in DIMENSIONS {
+        for &value in dimension.values() {
+            let request = dimension.request(value);
+            let actual = match dimension {
+                Dimension::Body => request.body.as_ref().map_or(0, Vec::len),
+                Dimension::Headers => request.headers.len(),
+                Dimension::Uri => request.uri.len(),
+            };
+            assert_eq!(value, actual, "{} = {value}", dimension.name());
+        }
+    }
+
+    let request = Dimension::Headers.request(2);
+    let echo = |headers: &[(&str, &str)], body: Option<&[u8]>| Response {
+        status: 200,
+        headers: Some(
+            headers
+                .iter()
+                .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+        ),
+        body: body.map(<[u8]>::to_vec),
+    };
+
+    let uri_length = ("uri-length", "5");
+    let (first, second) = (("x-header-0", "value-0"), ("x-header-1", "value-1"));
+
+    check_echo(&request, &echo(&[first, second, uri_length], None))?;
+    check_echo(&request, &echo(&[first, second, uri_length], Some(b"")))?;
+    assert!(check_echo(&request, &echo(&[first, uri_length], None)).is_err());
+    assert!(check_echo(&request, &echo(&[first, second], None)).is_err());
+    assert!(check_echo(&request, &echo(&[first, second, ("uri-length", "4")], None)).is_err());
+    assert!(check_echo(&request, &echo(&[first, second, uri_length], Some(b"x"))).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Throughput measurement: N workers on a multi-threaded Tokio runtime send requests concurrently to a single,
+//! shared `Dispatcher` (and thus a shared `Engine` and `InstancePre`), as a server would
+
+use {
+    super::{
+        bencher::Options,
+        check_test_response, compile_guests,
+        front_end::{Dispatcher, Strategy},
+        stdio_host, test_request,
+    },
+    anyhow::{Error, Result},
+    serde::Serialize,
+    std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+    tokio::runtime::Builder,
+};
+
+/// A component and strategy to measure throughput for
+pub struct ThroughputScenario {
+    pub name: &'static str,
+    pub wasm_path: &'static str,
+    pub strategy: Strategy,
+}
+
+/// All throughput scenarios, in the order the runner executes them
+///
+/// Note that `Strategy::Reuse` serializes requests on its single instance, so it shouldn't be expected to scale.
+pub const THROUGHPUT_SCENARIOS: &[ThroughputScenario] = &[
+    ThroughputScenario {
+        name: "spin_rust_throughput",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Fresh,
+    },
+    ThroughputScenario {
+        name: "spin_rust_throughput_pre_instance",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::PreInstance,
+    },
+    ThroughputScenario {
+        name: "spin_rust_throughput_pre_instance_with_pooling",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Pooling,
+    },
+    ThroughputScenario {
+        name: "spin_rust_throughput_reuse_instance",
+        wasm_path: "/wasm32-wasi/release/spin_guest.wasm",
+        strategy: Strategy::Reuse,
+    },
+];
+
+/// The number of requests completed by a given number of workers in a given time
+#[derive(Serialize, Clone, Debug)]
+pub struct Throughput {
+    pub concurrency: usize,
+    pub requests: u64,
+    pub elapsed_s: f64,
+    pub requests_per_second: f64,
+}
+
+/// Have `concurrency` workers send requests back to back until `duration` has elapsed, returning the total number
+/// of requests completed
+async fn run_workers(
+    dispatcher: &Arc<Dispatcher>,
+    concurrency: usize,
+    duration: Duration,
+) -> Result<u64> {
+    let deadline = Instant::now() + duration;
+
+    let workers = (0..concurrency)
+        .map(|_| {
+            let dispatcher = dispatcher.clone();
+            tokio::spawn(async move {
+                let request = test_request();
+                let mut requests = 0;
+                while Instant::now() < deadline {
+                    check_test_response(dispatcher.dispatch(&request).await?);
+                    requests += 1;
+                }
+                Ok::<_, Error>(requests)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut requests = 0;
+    for worker in workers {
+        requests += worker.await??;
+    }
+
+    Ok(requests)
+}
+
+impl ThroughputScenario {
+    /// Measure throughput using `concurrency` workers, each with its own runtime thread, warming up for
+    /// `options.warm_up` and then counting requests for `options.budget`
+    pub fn measure(&self, concurrency: usize, options: Options) -> Result<Throughput> {
+        compile_guests();
+
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(concurrency)
+            .enable_all()
+            .build()?;
+
+        runtime.block_on(async {
+            let dispatcher =
+                Arc::new(Dispatcher::new(self.wasm_path, self.strategy, stdio_host).await?);
+
+            run_workers(&dispatcher, concurrency, options.warm_up).await?;
+
+            let start = Instant::now();
+            let requests = run_workers(&dispatcher, concurrency, options.budget).await?;
+            let elapsed = start.elapsed().as_secs_f64();
+
+            Ok::<_, Error>(Throughput {
+                concurrency,
+                requests,
+                elapsed_s: elapsed,
+                requests_per_second: requests as f64 / elapsed,
+            })
+        })
+    }
+}
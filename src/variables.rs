@@ -0,0 +1,348 @@
+//! Provider layer for the `config` interface, modeled after Spin's application variables
+//!
+//! A schema declares application-level variables (with optional defaults) and the component's config keys, whose
+//! values are templates which may refer to those variables using `{{ name }}` syntax.  Variables are resolved by
+//! consulting each provider in turn, falling back to the declared default.
+
+use {
+    super::{config, Host},
+    anyhow::{bail, Context, Result},
+    async_trait::async_trait,
+    serde::Deserialize,
+    std::{collections::HashMap, env, fs, path::Path},
+};
+
+/// A source of variable values
+pub trait Provider: Send + Sync {
+    /// Return the value of the specified variable, or `Ok(None)` if this provider has no value for it
+    fn get(&self, name: &str) -> Result<Option<String>>;
+}
+
+/// Provides variable values from a flat TOML table of strings
+pub struct TomlProvider(HashMap<String, String>);
+
+impl TomlProvider {
+    pub fn parse(toml: &str) -> Result<Self> {
+        Ok(Self(toml::from_str(toml)?))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).with_context(|| path.display().to_string())
+    }
+}
+
+impl Provider for TomlProvider {
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.0.get(name).cloned())
+    }
+}
+
+/// Provides variable values from environment variables named `<prefix><NAME>`, e.g. `SPIN_CONFIG_GREETING`
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl Default for EnvProvider {
+    fn default() -> Self {
+        Self {
+            prefix: "SPIN_CONFIG_".to_owned(),
+        }
+    }
+}
+
+impl Provider for EnvProvider {
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        match env::var(format!("{}{}", self.prefix, name.to_ascii_uppercase())) {
+            Ok(value) => Ok(Some(value)),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Variable {
+    #[serde(default)]
+    default: Option<String>,
+    #[serde(default)]
+    required: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Schema {
+    #[serde(default)]
+    variables: HashMap<String, Variable>,
+    #[serde(default)]
+    config: HashMap<String, String>,
+}
+
+enum Part {
+    Literal(String),
+    Variable(String),
+}
+
+/// Resolves config keys to values on behalf of a single component
+#[derive(Default)]
+pub struct ConfigResolver {
+    variables: HashMap<String, Variable>,
+    /// Parsed templates, or a description of why a template failed validation
+    config: HashMap<String, Result<Vec<Part>, String>>,
+    providers: Vec<Box<dyn Provider>>,
+}
+
+impl ConfigResolver {
+    /// Create a resolver from a TOML schema with `[variables]` and `[config]` tables, e.g.:
+    ///
+    /// ```toml
+    /// [variables]
+    /// greeting = { default = "hola" }
+    /// place = { required = true }
+    ///
+    /// [config]
+    /// message = "{{ greeting }}, {{ place }}!"
+    /// ```
+    ///
+    /// Malformed variable declarations are reported here, whereas a malformed template is only reported (as
+    /// `invalid-schema`) when the guest asks for the corresponding key, mirroring how Spin treats each key
+    /// independently.
+    pub fn new(schema: &str) -> Result<Self> {
+        let Schema { variables, config } = toml::from_str(schema)?;
+
+        for (name, variable) in &variables {
+            if !valid_name(name) {
+                bail!("invalid variable name: {name:?}");
+            }
+
+            if variable.required && variable.default.is_some() {
+                bail!("variable {name:?} is required and so may not have a default");
+            }
+        }
+
+        let config = config
+            .into_iter()
+            .map(|(key, template)| {
+                let parts = parse_template(&template, &variables);
+                (key, parts)
+            })
+            .collect();
+
+        Ok(Self {
+            variables,
+            config,
+            providers: Vec::new(),
+        })
+    }
+
+    /// Add a provider, to be consulted after any previously-added providers
+    pub fn provider(mut self, provider: impl Provider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    fn resolve_variable(&self, name: &str) -> Result<String, config::Error> {
+        for provider in &self.providers {
+            if let Some(value) = provider
+                .get(name)
+                .map_err(|e| config::Error::Provider(format!("{e:?}")))?
+            {
+                return Ok(value);
+            }
+        }
+
+        self.variables
+            .get(name)
+            .and_then(|variable| variable.default.clone())
+            .ok_or_else(|| {
+                config::Error::Provider(format!("no provider resolved required variable {name:?}"))
+            })
+    }
+
+    pub fn get(&self, key: &str) -> Result<String, config::Error> {
+        if !valid_name(key) {
+            return Err(config::Error::InvalidKey(key.to_owned()));
+        }
+
+        let parts = self
+            .config
+            .get(key)
+            .ok_or_else(|| config::Error::InvalidKey(key.to_owned()))?
+            .as_ref()
+            .map_err(|e| config::Error::InvalidSchema(e.clone()))?;
+
+        let mut value = String::new();
+        for part in parts {
+            match part {
+                Part::Literal(literal) => value.push_str(literal),
+                Part::Variable(name) => value.push_str(&self.resolve_variable(name)?),
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+/// Config keys and variable names must start with a lowercase ASCII letter, followed by any number of lowercase
+/// ASCII letters, ASCII digits, and underscores
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some('a'..='z'))
+        && chars.all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_'))
+}
+
+fn parse_template(
+    template: &str,
+    variables: &HashMap<String, Variable>,
+) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_owned()));
+        }
+
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("unterminated reference in template {template:?}"))?;
+
+        let name = rest[start + 2..start + end].trim();
+
+        if !variables.contains_key(name) {
+            return Err(format!(
+                "template {template:?} refers to undeclared variable {name:?}"
+            ));
+        }
+
+        parts.push(Part::Variable(name.to_owned()));
+        rest = &rest[start + end + 2..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_owned()));
+    }
+
+    Ok(parts)
+}
+
+#[async_trait]
+impl config::Host for Host {
+    async fn get_config(&mut self, key: String) -> Result<Result<String, config::Error>> {
+        Ok(self.config.get(&key))
+    }
+}
+
+/// Environment variables set for the duration of a test, and removed (even if the test panics) when dropped
+///
+/// Tests run in parallel within the same process, so each test should use names no other test does.
+#[cfg(test)]
+struct EnvVars(Vec<&'static str>);
+
+#[cfg(test)]
+impl EnvVars {
+    fn set(vars: &[(&'static str, &str)]) -> Self {
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        Self(vars.iter().map(|(name, _)| *name).collect())
+    }
+}
+
+#[cfg(test)]
+impl Drop for EnvVars {
+    fn drop(&mut self) {
+        for name in &self.0 {
+            env::remove_var(name);
+        }
+    }
+}
+
+#[test]
+fn config_resolution() -> Result<()> {
+    let resolver = ConfigResolver::new(
+        r#"
+        [variables]
+        greeting = { default = "hello" }
+        place = { default = "world" }
+        punctuation = { default = "!" }
+        secret = { required = true }
+
+        [config]
+        message = "{{ greeting }}, {{place}}{{ punctuation }}"
+        password = "{{ secret }}"
+        broken = "{{ greeting"
+        dangling = "{{ nope }}"
+        "#,
+    )?
+    .provider(TomlProvider::parse(r#"greeting = "hola""#)?)
+    .provider(EnvProvider {
+        prefix: "WASMTIME_PERFORMANCE_CONFIG_RESOLUTION_".to_owned(),
+    });
+
+    let _vars = EnvVars::set(&[
+        ("WASMTIME_PERFORMANCE_CONFIG_RESOLUTION_GREETING", "ignored"),
+        ("WASMTIME_PERFORMANCE_CONFIG_RESOLUTION_PLACE", "mundo"),
+    ]);
+
+    // The TOML file wins over the environment, which wins over defaults:
+    assert_eq!("hola, mundo!", resolver.get("message").unwrap());
+
+    assert!(matches!(
+        resolver.get("password"),
+        Err(config::Error::Provider(_))
+    ));
+    assert!(matches!(
+        resolver.get("broken"),
+        Err(config::Error::InvalidSchema(_))
+    ));
+    assert!(matches!(
+        resolver.get("dangling"),
+        Err(config::Error::InvalidSchema(_))
+    ));
+    assert!(matches!(
+        resolver.get("missing"),
+        Err(config::Error::InvalidKey(_))
+    ));
+    assert!(matches!(
+        resolver.get("Not-Valid"),
+        Err(config::Error::InvalidKey(_))
+    ));
+
+    assert!(
+        ConfigResolver::new(r#"variables = { x = { required = true, default = "y" } }"#).is_err()
+    );
+    assert!(ConfigResolver::new(r#"variables = { X = {} }"#).is_err());
+
+    Ok(())
+}
+
+/// A provider which always fails, e.g. because a remote secret store is unreachable
+#[cfg(test)]
+struct FailingProvider;
+
+#[cfg(test)]
+impl Provider for FailingProvider {
+    fn get(&self, _name: &str) -> Result<Option<String>> {
+        Err(anyhow::anyhow!("provider unavailable"))
+    }
+}
+
+#[test]
+fn config_provider_error() -> Result<()> {
+    let resolver = ConfigResolver::new(
+        r#"
+        variables = { greeting = { default = "hola" } }
+        config = { message = "{{ greeting }}", literal = "no variables here" }
+        "#,
+    )?
+    .provider(FailingProvider);
+
+    assert!(matches!(
+        resolver.get("message"),
+        Err(config::Error::Provider(_))
+    ));
+    assert_eq!("no variables here", resolver.get("literal").unwrap());
+
+    Ok(())
+}
@@ -0,0 +1,433 @@
+//! [WAGI](https://github.com/deislabs/wagi) executor: maps requests to CGI-style arguments, environment variables,
+//! and stdin, then parses the guest's CGI-style output into a structured response
+
+use {
+    super::{front_end::IncomingRequest, Method, Response},
+    anyhow::{anyhow, bail, Context, Result},
+    std::{
+        fs,
+        io::{Cursor, ErrorKind, Write},
+        os::unix::process::CommandExt,
+        path::PathBuf,
+        process::{Command, Stdio},
+        thread,
+    },
+    wasmtime::{
+        Config, Engine, InstancePre as ModuleInstancePre, Linker as ModuleLinker, Module, Store,
+    },
+};
+
+fn method_name(method: Method) -> &'static str {
+    match method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+        Method::Patch => "PATCH",
+        Method::Head => "HEAD",
+        Method::Options => "OPTIONS",
+    }
+}
+
+/// Describes how a WAGI handler is mounted, which determines how a request path is split into `SCRIPT_NAME` and
+/// `PATH_INFO`
+pub struct Mount {
+    /// The route prefix the handler is mounted at, e.g. "" for a "/..." wildcard route
+    pub script_name: String,
+    pub server_name: String,
+    pub server_port: u16,
+}
+
+impl Default for Mount {
+    fn default() -> Self {
+        Self {
+            script_name: String::new(),
+            server_name: "localhost".to_owned(),
+            server_port: 80,
+        }
+    }
+}
+
+impl Mount {
+    /// Build the command-line arguments WAGI passes to a handler: the path, followed by each query parameter
+    pub fn args(&self, request: &IncomingRequest) -> Vec<String> {
+        let (path, query) = split_uri(&request.uri);
+        [path.to_owned()]
+            .into_iter()
+            .chain(
+                query
+                    .split('&')
+                    .filter(|param| !param.is_empty())
+                    .map(str::to_owned),
+            )
+            .collect()
+    }
+
+    /// Build the CGI 1.1 environment for the specified request, plus an `HTTP_*` variable for each header
+    pub fn environment(&self, request: &IncomingRequest) -> Vec<(String, String)> {
+        let (path, query) = split_uri(&request.uri);
+        // Only strip whole path segments, so that e.g. "/foobar" isn't treated as being under "/foo"
+        let path_info = path
+            .strip_prefix(&self.script_name)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+            .unwrap_or(path);
+        let header = |name: &str| {
+            request
+                .headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+
+        let mut environment = vec![
+            ("AUTH_TYPE", String::new()),
+            (
+                "CONTENT_LENGTH",
+                request.body.as_ref().map_or(0, Vec::len).to_string(),
+            ),
+            ("CONTENT_TYPE", header("content-type")),
+            ("GATEWAY_INTERFACE", "CGI/1.1".to_owned()),
+            ("PATH_INFO", path_info.to_owned()),
+            ("PATH_TRANSLATED", path_info.to_owned()),
+            ("QUERY_STRING", query.to_owned()),
+            ("REMOTE_ADDR", "127.0.0.1".to_owned()),
+            ("REMOTE_HOST", "localhost".to_owned()),
+            ("REMOTE_USER", String::new()),
+            ("REQUEST_METHOD", method_name(request.method).to_owned()),
+            ("SCRIPT_NAME", self.script_name.clone()),
+            ("SERVER_NAME", self.server_name.clone()),
+            ("SERVER_PORT", self.server_port.to_string()),
+            ("SERVER_PROTOCOL", "HTTP/1.1".to_owned()),
+            ("SERVER_SOFTWARE", "WAGI/1".to_owned()),
+            (
+                "X_FULL_URL",
+                format!(
+                    "http://{}:{}{}",
+                    self.server_name, self.server_port, request.uri
+                ),
+            ),
+            ("X_MATCHED_ROUTE", self.script_name.clone()),
+            ("X_RAW_PATH_INFO", path_info.to_owned()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v))
+        .collect::<Vec<_>>();
+
+        environment.extend(request.headers.iter().map(|(k, v)| {
+            (
+                format!("HTTP_{}", k.to_ascii_uppercase().replace('-', "_")),
+                v.clone(),
+            )
+        }));
+
+        environment
+    }
+}
+
+fn split_uri(uri: &str) -> (&str, &str) {
+    uri.split_once('?').unwrap_or((uri, ""))
+}
+
+/// Parse CGI output (i.e. headers, a blank line, and the body) into a response
+///
+/// The `status` header, if present, sets the status code and is not passed on as a response header.  Otherwise,
+/// the status is 302 if a `location` header is present, or 200 if not.
+pub fn parse_output(output: &[u8]) -> Result<Response> {
+    let (head, body) = [b"\r\n\r\n" as &[_], b"\n\n"]
+        .iter()
+        .filter_map(|separator| {
+            output
+                .windows(separator.len())
+                .position(|window| window == *separator)
+                .map(|index| (index, separator.len()))
+        })
+        .min()
+        .map(|(index, length)| (&output[..index], &output[index + length..]))
+        .ok_or_else(|| anyhow!("CGI output has no blank line separating headers from body"))?;
+
+    let mut status = None;
+    let mut location = false;
+    let mut headers = Vec::new();
+
+    for line in std::str::from_utf8(head)?.lines() {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("malformed CGI header: {line:?}"))?;
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("status") {
+            status = Some(
+                value
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .parse::<u16>()
+                    .with_context(|| format!("malformed CGI status: {value:?}"))?,
+            );
+        } else {
+            if name.eq_ignore_ascii_case("location") {
+                location = true;
+            }
+            headers.push((name.to_owned(), value.to_owned()));
+        }
+    }
+
+    let status = match (status, location) {
+        (Some(status), _) => status,
+        (None, true) => 302,
+        (None, false) => 200,
+    };
+
+    if !(100..1000).contains(&status) {
+        bail!("invalid CGI status: {status}");
+    }
+
+    Ok(Response {
+        status,
+        headers: Some(headers),
+        body: Some(body.to_vec()),
+    })
+}
+
+/// A WAGI module, pre-instantiated and ready to handle requests
+pub struct WagiExecutor {
+    engine: Engine,
+    pre: ModuleInstancePre<wasi_preview1::WasiCtx>,
+    mount: Mount,
+}
+
+impl WagiExecutor {
+    pub fn new(wasm_path: &str, mut config: Config, mount: Mount) -> Result<Self> {
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let mut linker = ModuleLinker::new(&engine);
+        wasmtime_wasi_preview1::add_to_linker(&mut linker, |ctx| ctx)?;
+        let pre = linker.instantiate_pre(&Module::new(
+            &engine,
+            fs::read(format!("{}{wasm_path}", env!("OUT_DIR")))?,
+        )?)?;
+
+        Ok(Self { engine, pre, mount })
+    }
+
+    pub async fn execute(&self, request: &IncomingRequest) -> Result<Response> {
+        let mut ctx = wasmtime_wasi_preview1::WasiCtxBuilder::new().build();
+        for arg in self.mount.args(request) {
+            ctx.push_arg(&arg)?;
+        }
+        for (key, value) in self.mount.environment(request) {
+            ctx.push_env(&key, &value)?;
+        }
+        ctx.set_stdin(Box::new(wasi_preview1::pipe::ReadPipe::new(Cursor::new(
+            request.body.clone().unwrap_or_default(),
+        ))));
+        let stdout = wasi_preview1::pipe::WritePipe::new_in_memory();
+        ctx.set_stdout(Box::new(stdout.clone()));
+        let stderr = wasi_preview1::pipe::WritePipe::new_in_memory();
+        ctx.set_stderr(Box::new(stderr.clone()));
+
+        let mut store = Store::new(&self.engine, ctx);
+        let instance = self.pre.instantiate_async(&mut store).await?;
+
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+
+        let result = start.call_async(&mut store, ()).await;
+
+        drop(store);
+
+        result.with_context(|| {
+            String::from_utf8_lossy(&stderr.try_into_inner().unwrap().into_inner()).to_string()
+        })?;
+
+        parse_output(&stdout.try_into_inner().unwrap().into_inner())
+    }
+}
+
+/// A native CGI program, executed in a new process for each request, as a classic CGI server would
+pub struct CgiExecutor {
+    program: PathBuf,
+    mount: Mount,
+}
+
+impl CgiExecutor {
+    pub fn new(program: impl Into<PathBuf>, mount: Mount) -> Self {
+        Self {
+            program: program.into(),
+            mount,
+        }
+    }
+
+    pub fn execute(&self, request: &IncomingRequest) -> Result<Response> {
+        let mut args = self.mount.args(request).into_iter();
+
+        // As with WAGI, the path is passed as `argv[0]` rather than the program's actual path
+        let mut command = Command::new(&self.program);
+        if let Some(arg0) = args.next() {
+            command.arg0(arg0);
+        }
+
+        let mut child = command
+            .args(args)
+            .env_clear()
+            .envs(self.mount.environment(request))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("unable to spawn {}", self.program.display()))?;
+
+        // The body is written on its own thread while we collect the output, so a program which writes as it reads
+        // can't fill its stdout pipe and deadlock with us
+        let stdin = child.stdin.take().unwrap();
+        let (written, output) = thread::scope(|scope| {
+            let writer = scope.spawn(move || {
+                let mut stdin = stdin;
+                match &request.body {
+                    Some(body) => stdin.write_all(body),
+                    None => Ok(()),
+                }
+            });
+            let output = child.wait_with_output();
+            (writer.join().unwrap(), output)
+        });
+        let output = output?;
+
+        // A program is free to exit without reading all of its body, in which case we'll have seen a broken pipe
+        match written {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+                return Err(e).context("unable to write request body")
+            }
+            _ => (),
+        }
+
+        if !output.status.success() {
+            bail!(
+                "CGI program exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        parse_output(&output.stdout)
+    }
+}
+
+#[test]
+fn cgi_mapping() -> Result<()> {
+    let request = IncomingRequest {
+        method: Method::Post,
+        uri: "/app/foo/bar?a=b&c=d".to_owned(),
+        headers: vec![
+            ("content-type".to_owned(), "text/plain".to_owned()),
+            ("x-forwarded-for".to_owned(), "10.0.0.1".to_owned()),
+        ],
+        body: Some(b"hello".to_vec()),
+    };
+
+    let mount = Mount {
+        script_name: "/app".to_owned(),
+        ..Mount::default()
+    };
+
+    assert_eq!(vec!["/app/foo/bar", "a=b", "c=d"], mount.args(&request));
+
+    let environment = mount.environment(&request);
+    let var = |name: &str| {
+        environment
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    assert_eq!(Some("POST"), var("REQUEST_METHOD"));
+    assert_eq!(Some("/app"), var("SCRIPT_NAME"));
+    assert_eq!(Some("/foo/bar"), var("PATH_INFO"));
+    assert_eq!(Some("a=b&c=d"), var("QUERY_STRING"));
+    assert_eq!(Some("5"), var("CONTENT_LENGTH"));
+    assert_eq!(Some("text/plain"), var("CONTENT_TYPE"));
+    assert_eq!(Some("10.0.0.1"), var("HTTP_X_FORWARDED_FOR"));
+
+    let path_info = |uri: &str| {
+        mount
+            .environment(&IncomingRequest {
+                method: Method::Get,
+                uri: uri.to_owned(),
+                headers: Vec::new(),
+                body: None,
+            })
+            .into_iter()
+            .find_map(|(k, v)| (k == "PATH_INFO").then_some(v))
+    };
+    assert_eq!(Some("".to_owned()), path_info("/app"));
+    assert_eq!(Some("/".to_owned()), path_info("/app/"));
+    assert_eq!(Some("/appendix".to_owned()), path_info("/appendix"));
+
+    let response = parse_output(b"content-type: text/plain\nstatus: 404 Not Found\n\nnope")?;
+    assert_eq!(404, response.status);
+    assert_eq!(
+        Some(vec![("content-type".to_owned(), "text/plain".to_owned())]),
+        response.headers
+    );
+    assert_eq!(Some(b"nope" as &[_]), response.body.as_deref());
+
+    let response = parse_output(b"Location: /elsewhere\r\n\r\n")?;
+    assert_eq!(302, response.status);
+    assert_eq!(Some(b"" as &[_]), response.body.as_deref());
+
+    let response = parse_output(b"content-type: text/plain\n\nline one\n\nline two")?;
+    assert_eq!(200, response.status);
+    assert_eq!(
+        Some(b"line one\n\nline two" as &[_]),
+        response.body.as_deref()
+    );
+
+    assert!(parse_output(b"no blank line").is_err());
+    assert!(parse_output(b"status: teapot\n\n").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn cgi_execution() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Note that the kernel passes a script's own path as `$0`, so we can only check the rest of the arguments here
+    let dir = tempfile::tempdir()?;
+    let program = dir.path().join("handler");
+    fs::write(
+        &program,
+        "#!/bin/sh\n\
+         test \"$*\" = a=b || exit 3\n\
+         test -z \"$HOME\" || exit 4\n\
+         printf 'status: 201\\n\\n%s:' \"$REQUEST_METHOD\"\n\
+         cat\n",
+    )?;
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755))?;
+
+    let executor = CgiExecutor::new(&program, Mount::default());
+    let mut request = IncomingRequest {
+        method: Method::Put,
+        uri: "/foo?a=b".to_owned(),
+        headers: Vec::new(),
+        body: Some(b"hello".to_vec()),
+    };
+
+    let response = executor.execute(&request)?;
+    assert_eq!(201, response.status);
+    assert_eq!(Some(b"PUT:hello" as &[_]), response.body.as_deref());
+
+    // A body much larger than a pipe's buffer, which `cat` echoes while we're still writing it
+    let body = vec![b'x'; 4 * 1024 * 1024];
+    request.body = Some(body.clone());
+    let response = executor.execute(&request)?;
+    assert_eq!(
+        Some(&[b"PUT:" as &[_], &body].concat()),
+        response.body.as_ref()
+    );
+
+    request.uri = "/bar".to_owned();
+    assert!(executor.execute(&request).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,249 @@
+//! Pre-forked worker pool isolation: a "zygote" process keeps a pool of forked workers, each of which handles exactly
+//! one request and then exits, so `fork` and `waitpid` happen off the hot path
+//!
+//! The zygote is itself forked from the benchmark process once everything that can be prepared ahead of time (e.g.
+//! an `InstancePre`) has been created.  It then forks workers until the pool is full, and forks a replacement each
+//! time one exits.  Workers share two channels with the parent:
+//!
+//! * ready: a pipe to which each worker writes a byte once forked, so the parent can wait for a worker to be
+//!   available
+//! * requests: a Unix socket on which every worker accepts; the parent connects once per request, the kernel hands
+//!   the connection to exactly one idle worker, and that worker writes a status byte back once it has handled the
+//!   request
+//!
+//! Since each request has its own connection, a worker which dies at any point after accepting one (e.g. because it
+//! was killed by a signal) closes it, so the parent sees EOF rather than waiting forever, while one which dies before
+//! accepting leaves the request queued for another worker.
+
+use {
+    anyhow::{bail, ensure, Context, Result},
+    std::{
+        fs::File,
+        io::{Read, Write},
+        os::unix::{
+            io::FromRawFd,
+            net::{UnixListener, UnixStream},
+        },
+        panic::{self, AssertUnwindSafe},
+        path::PathBuf,
+    },
+    tempfile::TempDir,
+};
+
+/// Number of workers the zygote keeps forked and waiting for a request
+pub const POOL_SIZE: usize = 8;
+
+const SUCCEEDED: u8 = 0;
+const FAILED: u8 = 1;
+
+/// Create a pipe, returning its read and write ends
+fn pipe() -> Result<(File, File)> {
+    let mut fds = [0; 2];
+    if -1 == unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } {
+        bail!("pipe2 failed; errno: {}", errno::errno());
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Ask for this (newly forked) process to be killed when `parent` exits, so nothing outlives the benchmark process
+fn die_with(parent: libc::pid_t) {
+    unsafe {
+        if -1 == libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) || libc::getppid() != parent {
+            libc::_exit(1)
+        }
+    }
+}
+
+/// Handle for the benchmark process to hand requests to a zygote's workers
+pub struct Zygote {
+    pid: libc::pid_t,
+    ready: File,
+    socket: PathBuf,
+    _dir: TempDir,
+}
+
+impl Zygote {
+    /// Fork a zygote which keeps `pool_size` workers waiting to call `fun`
+    pub fn start(pool_size: usize, mut fun: impl FnMut()) -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("requests.sock");
+        let requests = UnixListener::bind(&socket).context("unable to bind request socket")?;
+        let (ready_read, ready_write) = pipe()?;
+        let parent = unsafe { libc::getpid() };
+
+        match unsafe { libc::fork() } {
+            -1 => bail!("fork failed; errno: {}", errno::errno()),
+            0 => {
+                // I'm the zygote
+                die_with(parent);
+                drop(ready_read);
+
+                let workers = Workers {
+                    ready: ready_write,
+                    requests,
+                };
+
+                workers.maintain(pool_size, &mut fun)
+            }
+            pid => {
+                // I'm the parent
+                drop((requests, ready_write));
+
+                Ok(Self {
+                    pid,
+                    ready: ready_read,
+                    socket,
+                    _dir: dir,
+                })
+            }
+        }
+    }
+
+    /// Wait until a worker is available to handle a request
+    ///
+    /// Each worker announces itself exactly once and handles at most one request, so calling this once before each
+    /// call to `request` guarantees an idle worker will pick it up immediately.
+    pub fn wait_ready(&self) -> Result<()> {
+        let mut byte = [0];
+        (&self.ready).read_exact(&mut byte)?;
+        Ok(())
+    }
+
+    /// Hand a request to an idle worker and wait for it to finish
+    pub fn request(&self) -> Result<()> {
+        let mut connection = UnixStream::connect(&self.socket)?;
+
+        let mut status = [0];
+        connection
+            .read_exact(&mut status)
+            .context("worker exited without reporting a status")?;
+        ensure!(status[0] == SUCCEEDED, "worker failed");
+
+        Ok(())
+    }
+}
+
+impl Drop for Zygote {
+    fn drop(&mut self) {
+        // The workers are killed along with the zygote, having each asked for that using `die_with`
+        unsafe {
+            libc::kill(self.pid, libc::SIGKILL);
+            libc::waitpid(self.pid, &mut 0, 0);
+        }
+    }
+}
+
+/// The zygote's ends of the channels, which every worker inherits
+struct Workers {
+    ready: File,
+    requests: UnixListener,
+}
+
+impl Workers {
+    /// Keep `pool_size` workers forked, never returning
+    fn maintain(&self, pool_size: usize, fun: &mut impl FnMut()) -> ! {
+        let zygote = unsafe { libc::getpid() };
+        let mut workers = 0;
+
+        loop {
+            while workers < pool_size {
+                match unsafe { libc::fork() } {
+                    -1 => unsafe { libc::_exit(1) },
+                    0 => {
+                        die_with(zygote);
+                        self.work(fun)
+                    }
+                    _ => workers += 1,
+                }
+            }
+
+            // However a worker exits, any request it accepted has already been answered, either by the worker or by
+            // the kernel closing the connection
+            if -1 == unsafe { libc::waitpid(-1, &mut 0, 0) } {
+                unsafe { libc::_exit(1) }
+            }
+            workers -= 1;
+        }
+    }
+
+    /// Announce readiness, wait for a request, and handle it, never returning
+    fn work(&self, fun: &mut impl FnMut()) -> ! {
+        let _ = (&self.ready).write_all(&[0]);
+
+        let Ok((mut connection, _)) = self.requests.accept() else {
+            unsafe { libc::_exit(1) }
+        };
+
+        // A panic must not unwind past this point, or the worker would carry on running the zygote's loop
+        let status = if panic::catch_unwind(AssertUnwindSafe(fun)).is_ok() {
+            SUCCEEDED
+        } else {
+            FAILED
+        };
+
+        let _ = connection.write_all(&[status]);
+
+        // Exit without running any destructors for maximum performance
+        unsafe { libc::_exit(0) }
+    }
+}
+
+#[test]
+fn zygote_pool() -> Result<()> {
+    use std::fs;
+
+    let zygote = Zygote::start(2, || ())?;
+    for _ in 0..10 {
+        zygote.wait_ready()?;
+        zygote.request()?;
+    }
+
+    let zygote = Zygote::start(2, || panic!("oops"))?;
+    zygote.wait_ready()?;
+    assert!(zygote.request().is_err());
+
+    // A worker which dies while idle doesn't cause the next request to fail
+    let zygote = Zygote::start(2, || ())?;
+    zygote.wait_ready()?;
+    zygote.wait_ready()?;
+    let children = fs::read_to_string(format!("/proc/{0}/task/{0}/children", zygote.pid));
+    // Listing children requires `CONFIG_PROC_CHILDREN`, without which there's nothing more we can check here
+    if let Some(idle) = children
+        .ok()
+        .and_then(|children| children.split_whitespace().next()?.parse().ok())
+    {
+        unsafe { libc::kill(idle, libc::SIGKILL) };
+        for _ in 0..5 {
+            zygote.wait_ready()?;
+            zygote.request()?;
+        }
+    }
+
+    // A worker which exits without reporting a status
+    let zygote = Zygote::start(2, || unsafe { libc::_exit(2) })?;
+    zygote.wait_ready()?;
+    assert!(zygote.request().is_err());
+
+    // A worker killed as soon as it has taken a request fails that request, without hanging or affecting the next
+    // one (only the first worker to take a request gets to create the marker file)
+    let dir = tempfile::tempdir()?;
+    let marker = dir.path().join("killed");
+    let zygote = Zygote::start(2, || {
+        if File::options()
+            .write(true)
+            .create_new(true)
+            .open(&marker)
+            .is_ok()
+        {
+            unsafe { libc::kill(libc::getpid(), libc::SIGKILL) };
+        }
+    })?;
+    zygote.wait_ready()?;
+    assert!(zygote.request().is_err());
+    for _ in 0..5 {
+        zygote.wait_ready()?;
+        zygote.request()?;
+    }
+
+    Ok(())
+}
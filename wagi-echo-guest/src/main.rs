@@ -0,0 +1,37 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+};
+
+/// Echo the request's headers and body, plus a `uri-length` header so the host can check we saw the whole URI
+fn main() {
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{name} should be set"));
+
+    let mut body = Vec::new();
+    io::stdin().lock().read_to_end(&mut body).unwrap();
+    assert_eq!(var("CONTENT_LENGTH"), body.len().to_string());
+
+    let query = var("QUERY_STRING");
+    let uri_length = var("SCRIPT_NAME").len()
+        + var("PATH_INFO").len()
+        + if query.is_empty() { 0 } else { query.len() + 1 };
+
+    let mut stdout = io::stdout().lock();
+    write!(
+        stdout,
+        "content-type: application/octet-stream\nstatus: 200\n"
+    )
+    .unwrap();
+    for (name, value) in env::vars() {
+        if let Some(name) = name.strip_prefix("HTTP_") {
+            writeln!(
+                stdout,
+                "{}: {value}",
+                name.to_ascii_lowercase().replace('_', "-")
+            )
+            .unwrap();
+        }
+    }
+    write!(stdout, "uri-length: {uri_length}\n\n").unwrap();
+    stdout.write_all(&body).unwrap();
+}
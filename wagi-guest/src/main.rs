@@ -1,18 +1,21 @@
 use std::{env, io};
 
+fn var(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("{name} should be set"))
+}
+
 fn main() {
     assert_eq!("/foo a=b", &env::args().collect::<Vec<_>>().join(" "));
-    assert_eq!(
-        "what=up",
-        &env::vars()
-            .map(|(k, v)| format!("{k}={v}"))
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
+    assert_eq!("POST", var("REQUEST_METHOD"));
+    assert_eq!("", var("SCRIPT_NAME"));
+    assert_eq!("/foo", var("PATH_INFO"));
+    assert_eq!("a=b", var("QUERY_STRING"));
+    assert_eq!("13", var("CONTENT_LENGTH"));
+    assert_eq!("up", var("HTTP_WHAT"));
     assert_eq!(
         "hello, world!",
         &io::read_to_string(&mut io::stdin().lock()).unwrap()
     );
 
-    print!("content-type: text/plain\n\nhola, mundo!\n");
+    print!("content-type: text/plain\nstatus: 200\n\nhola, mundo!");
 }